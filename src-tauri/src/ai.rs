@@ -1,106 +1,513 @@
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
-use crate::commands::TERMINAL_MANAGER;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter};
+use tokio_util::sync::CancellationToken;
+use crate::commands::{execute_and_wait_in_session, CommandSource, TerminalSession, TerminalSignal, DEFAULT_EXECUTE_TIMEOUT_MS, TERMINAL_MANAGER};
+use crate::conversation::{self, Conversation, CONVERSATIONS};
+use crate::storage::{self, ToolInvocation};
+use crate::providers::{self, ChatProvider, ProviderKind};
+use crate::profiles;
+use crate::secrets;
+use crate::policy::{self, ApprovalContext, ApprovalPolicy, Classification};
+use crate::sandbox::{self, SandboxOptions};
+use crate::mcp_client;
+use crate::error::{AppError, AppResult};
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AIConfig {
+    // 模型服务类型，决定请求格式和鉴权方式
+    #[serde(default)]
+    pub provider: ProviderKind,
+    // 只接收不输出：保存在系统密钥环中，不写入配置文件也不返回给前端
+    #[serde(default, skip_serializing)]
     pub api_key: String,
     pub model: String,
     pub base_url: String,
     pub max_tokens: u32,
     pub temperature: f32,
+    // 模型上下文窗口大小（token），历史超出时压缩较早的消息
+    #[serde(default = "default_context_window")]
+    pub context_window: u32,
+    // Azure OpenAI 的部署名（为空时使用 model）和接口版本
+    #[serde(default)]
+    pub deployment: Option<String>,
+    #[serde(default)]
+    pub api_version: Option<String>,
+    // AI 执行命令的自动批准/拒绝规则
+    #[serde(default)]
+    pub approval: ApprovalPolicy,
+}
+
+// 手动实现以免 API key 出现在日志中
+impl std::fmt::Debug for AIConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AIConfig")
+            .field("provider", &self.provider)
+            .field("api_key", &secrets::redact(&self.api_key))
+            .field("model", &self.model)
+            .field("base_url", &self.base_url)
+            .field("max_tokens", &self.max_tokens)
+            .field("temperature", &self.temperature)
+            .field("context_window", &self.context_window)
+            .field("deployment", &self.deployment)
+            .field("api_version", &self.api_version)
+            .field("approval", &self.approval)
+            .finish()
+    }
+}
+
+// 返回给前端的配置，API key 只保留脱敏形式
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactedAIConfig {
+    #[serde(flatten)]
+    pub config: AIConfig,
+    pub api_key: Option<String>,
+    pub has_api_key: bool,
+}
+
+impl From<&AIConfig> for RedactedAIConfig {
+    fn from(config: &AIConfig) -> Self {
+        Self {
+            config: config.clone(),
+            api_key: secrets::redact(&config.api_key),
+            has_api_key: !config.api_key.is_empty(),
+        }
+    }
+}
+
+fn default_context_window() -> u32 {
+    32_000
 }
 
 impl Default for AIConfig {
     fn default() -> Self {
         Self {
+            provider: ProviderKind::OpenAI,
             api_key: String::new(),
             model: "deepseek-chat".to_string(),
             base_url: "https://api.deepseek.com".to_string(),
             max_tokens: 1000,
             temperature: 0.7,
+            context_window: default_context_window(),
+            deployment: None,
+            api_version: None,
+            approval: ApprovalPolicy::default(),
         }
     }
 }
 
+// 单轮对话中工具调用的最大轮数，防止模型无限循环调用
+const MAX_TOOL_ROUNDS: usize = 8;
+
+// 压缩历史时保留的上下文比例，避免每轮都重新生成摘要
+const COMPACT_TARGET_RATIO: usize = 2;
+
+// 生成摘要时单条消息的最大字符数
+const SUMMARY_MESSAGE_MAX_CHARS: usize = 2000;
+
+// get_recent_output 一次最多返回的行数
+const MAX_RECENT_OUTPUT_LINES: usize = 2000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
-    pub content: String,
+    // 带 tool_calls 的 assistant 消息可能没有 content
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    pub fn assistant(content: String, tool_calls: Vec<ToolCall>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: if content.is_empty() { None } else { Some(content) },
+            tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+            tool_call_id: None,
+        }
+    }
+
+    pub fn tool_result(tool_call_id: &str, content: &str) -> Self {
+        Self {
+            role: "tool".to_string(),
+            content: Some(content.to_string()),
+            tool_calls: None,
+            tool_call_id: Some(tool_call_id.to_string()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChatRequest {
-    pub model: String,
-    pub messages: Vec<ChatMessage>,
-    pub max_tokens: u32,
-    pub temperature: f32,
-    pub stream: bool,
+pub struct ToolCall {
+    pub id: String,
+    pub r#type: String,
+    pub function: FunctionCall,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChatResponse {
-    pub choices: Vec<Choice>,
+pub struct FunctionCall {
+    pub name: String,
+    // 模型返回的 JSON 字符串形式参数
+    pub arguments: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Choice {
-    pub message: ChatMessage,
+pub struct Tool {
+    pub r#type: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+impl Tool {
+    pub(crate) fn function(name: &str, description: &str, parameters: serde_json::Value) -> Self {
+        Self {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: name.to_string(),
+                description: description.to_string(),
+                parameters,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ErrorResponse {
-    pub error: ErrorDetail,
+pub struct ToolCallDelta {
+    pub index: usize,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub function: Option<FunctionCallDelta>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ErrorDetail {
-    pub message: String,
-    pub r#type: String,
-    pub param: Option<String>,
-    pub code: String,
+pub struct FunctionCallDelta {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+// 发送给前端的增量事件
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatDeltaEvent {
+    pub request_id: String,
+    pub content: Option<String>,
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+// 一轮对话的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatReply {
+    pub conversation_id: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatDoneEvent {
+    pub request_id: String,
+    pub conversation_id: String,
+    pub content: String,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatErrorEvent {
+    pub request_id: String,
+    pub error: AppError,
+    pub cancelled: bool,
+}
+
+// 一次流式对话的上下文：事件发送目标、取消令牌和累计用量
+pub struct ChatStream {
+    app_handle: AppHandle,
+    request_id: String,
+    cancel: CancellationToken,
+    usage: std::sync::Mutex<Usage>,
+}
+
+impl ChatStream {
+    pub(crate) fn add_usage(&self, usage: &Usage) {
+        self.usage.lock().unwrap().add(usage);
+    }
+
+    pub(crate) fn emit_delta(&self, content: Option<String>, tool_calls: Option<Vec<ToolCallDelta>>) {
+        let event = ChatDeltaEvent {
+            request_id: self.request_id.clone(),
+            content,
+            tool_calls,
+        };
+        if let Err(e) = self.app_handle.emit("ai-chat-delta", &event) {
+            eprintln!("Failed to emit chat delta: {}", e);
+        }
+    }
 }
 
+// 进行中的流式对话，用于 cancel_chat
+static ACTIVE_CHATS: Lazy<std::sync::Mutex<HashMap<String, CancellationToken>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
 // MCP Server 功能 - 终端控制
 pub struct TerminalMCPServer;
 
 impl TerminalMCPServer {
-    pub async fn execute_command(command: &str) -> Result<String, String> {
-        let mut manager = TERMINAL_MANAGER.lock().unwrap();
-        
-        if let Some(session_id) = manager.get_active_session().cloned() {
-            // 通知插件命令开始
-            if let Some(session) = manager.get_session_mut(&session_id){
-                for plugin in &mut session.plugins {
-                    plugin.on_command_start(command, &session_id);
-                }
-            }
-            
-            let command_with_newline = format!("{}\n", command);
-            manager.write_to_session(&session_id, &command_with_newline)?;
-            
-            Ok(format!("Command '{}' sent to terminal", command))
+    // 以 OpenAI function calling 格式描述的终端工具
+    pub fn tools() -> Vec<Tool> {
+        vec![
+            Tool::function(
+                "execute_command",
+                "Run a shell command in the user's active terminal, wait for it to finish and return its output and exit code.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "command": { "type": "string", "description": "The shell command line to run" }
+                    },
+                    "required": ["command"]
+                }),
+            ),
+            Tool::function(
+                "execute_command_sandboxed",
+                "Run a shell command in a disposable sandbox instead of the user's terminal. Only the working directory and system directories are readable and read-only, only $TMPDIR is writable, most environment variables are removed, network access is off unless allow_network is true, and memory, CPU time and run time are limited. It does not run in the home directory itself. Prefer this for exploring or testing when the user's shell state does not matter.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "command": { "type": "string", "description": "The shell command line to run" },
+                        "allow_network": { "type": "boolean", "description": "Allow network access", "default": false }
+                    },
+                    "required": ["command"]
+                }),
+            ),
+            Tool::function(
+                "get_current_directory",
+                "Get the current working directory of the active terminal.",
+                serde_json::json!({ "type": "object", "properties": {} }),
+            ),
+            Tool::function(
+                "list_files",
+                "List the files in the current working directory of the active terminal.",
+                serde_json::json!({ "type": "object", "properties": {} }),
+            ),
+            Tool::function(
+                "read_screen",
+                "Read the text currently visible on the active terminal screen.",
+                serde_json::json!({ "type": "object", "properties": {} }),
+            ),
+            Tool::function(
+                "get_recent_output",
+                "Read the last lines of raw output from the active terminal.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "lines": { "type": "integer", "description": "Number of lines to return (at most 2000)", "default": 50 }
+                    }
+                }),
+            ),
+            Tool::function(
+                "get_last_command_output",
+                "Get the command line, output and exit code of the last command that finished in the active terminal.",
+                serde_json::json!({ "type": "object", "properties": {} }),
+            ),
+            Tool::function(
+                "send_signal",
+                "Send a signal to the foreground process of the active terminal, e.g. SIGINT to interrupt a running command.",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "signal": {
+                            "type": "string",
+                            "enum": ["SIGINT", "SIGTSTP", "SIGQUIT", "SIGWINCH", "SIGCONT", "SIGHUP", "SIGTERM", "SIGKILL"]
+                        }
+                    },
+                    "required": ["signal"]
+                }),
+            ),
+        ]
+    }
+
+    // 按名称执行工具调用，arguments 为模型返回的 JSON 字符串
+    pub async fn call_tool(name: &str, arguments: &str) -> AppResult<String> {
+        let args: serde_json::Value = if arguments.trim().is_empty() {
+            serde_json::json!({})
         } else {
-            Err("No active terminal session".to_string())
+            serde_json::from_str(arguments)
+                .map_err(|e| AppError::invalid_argument(format!("Invalid tool arguments: {}", e)))?
+        };
+
+        // 外部 MCP 客户端可以通过 session_id 指定终端，否则使用活动终端
+        let session_id = args.get("session_id").and_then(|v| v.as_str());
+
+        match name {
+            "execute_command" => {
+                let command = args.get("command")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| AppError::invalid_argument("Missing 'command' argument"))?;
+                Self::execute_command(session_id, command).await
+            }
+            "execute_command_sandboxed" => {
+                let command = args.get("command")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| AppError::invalid_argument("Missing 'command' argument"))?;
+                let options = SandboxOptions {
+                    allow_network: args.get("allow_network").and_then(|v| v.as_bool()).unwrap_or(false),
+                    ..SandboxOptions::default()
+                };
+                Self::execute_command_sandboxed(session_id, command, &options).await
+            }
+            "get_current_directory" | "get_cwd" => Self::get_current_directory(session_id).await,
+            "list_files" => Self::list_files(session_id).await,
+            "list_sessions" => Self::list_sessions().await,
+            "read_screen" => Self::read_screen(session_id).await,
+            "get_recent_output" => {
+                let lines = args.get("lines").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+                Self::get_recent_output(session_id, lines).await
+            }
+            "get_last_command_output" => Self::get_last_command_output(session_id).await,
+            "send_signal" => {
+                let signal: TerminalSignal = serde_json::from_value(
+                    args.get("signal").cloned().unwrap_or(serde_json::Value::Null),
+                ).map_err(|e| AppError::invalid_argument(format!("Invalid signal: {}", e)))?;
+                Self::send_signal(session_id, signal).await
+            }
+            _ => Err(AppError::invalid_argument(format!("Unknown tool: {}", name))),
         }
     }
 
-    pub async fn get_current_directory() -> Result<String, String> {
-        let manager = TERMINAL_MANAGER.lock().unwrap();
-        
-        if let Some(session_id) = manager.get_active_session() {
-            if let Some(session) = manager.get_session(session_id) {
-                Ok(session.config.working_dir.clone().unwrap_or_else(|| "Unknown".to_string()))
-            } else {
-                Err("Session not found".to_string())
+    // 需要经过审批的工具调用中将要执行的命令及其风险分级
+    pub fn proposed_command(name: &str, arguments: &str) -> Option<(String, Classification)> {
+        let args: serde_json::Value = serde_json::from_str(arguments).ok()?;
+        if name == "send_signal" {
+            let signal = args.get("signal").and_then(|v| v.as_str())?;
+            return Some((format!("send_signal {}", signal), policy::classify_signal(signal)));
+        }
+        let command = args.get("command").and_then(|v| v.as_str())?.to_string();
+        match name {
+            "execute_command" => {
+                let classification = policy::classify(&command);
+                Some((command, classification))
             }
-        } else {
-            Err("No active terminal session".to_string())
+            "execute_command_sandboxed" => {
+                let allow_network = args.get("allow_network").and_then(|v| v.as_bool()).unwrap_or(false);
+                let classification = policy::classify_sandboxed(&command, allow_network, sandbox::filesystem_confined());
+                Some((command, classification))
+            }
+            _ => None,
         }
     }
 
-    pub async fn list_files() -> Result<String, String> {
-        let current_dir = Self::get_current_directory().await?;
+    pub async fn execute_command(session_id: Option<&str>, command: &str) -> AppResult<String> {
+        let session_id = Self::resolve_session(session_id)?;
+
+        let result = execute_and_wait_in_session(&session_id, command, DEFAULT_EXECUTE_TIMEOUT_MS, CommandSource::Ai).await?;
+        let status = if result.timed_out && result.interrupted {
+            format!(
+                "timed out after {}ms, sent SIGINT to stop it; if it keeps running, use send_signal with SIGTERM or SIGKILL",
+                result.duration_ms
+            )
+        } else if result.timed_out {
+            format!(
+                "timed out after {}ms, command is still running; use send_signal to interrupt it before running another command",
+                result.duration_ms
+            )
+        } else {
+            format!(
+                "exit code {} in {}ms",
+                result.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "unknown".to_string()),
+                result.duration_ms
+            )
+        };
+        Ok(format!("$ {}\n{}\n[{}]", command, result.output, status))
+    }
+
+    // 在终端的当前目录下以沙箱方式执行，不影响交互终端
+    pub async fn execute_command_sandboxed(session_id: Option<&str>, command: &str, options: &SandboxOptions) -> AppResult<String> {
+        let cwd = Self::get_current_directory(session_id).await?;
+
+        let result = sandbox::run(command, std::path::Path::new(&cwd), options).await?;
+        let status = if result.timed_out {
+            format!("killed after timeout of {}ms", options.timeout_ms)
+        } else if let Some(signal) = result.signal {
+            format!("terminated by signal {} after {}ms", signal, result.duration_ms)
+        } else {
+            format!(
+                "exit code {} in {}ms",
+                result.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "unknown".to_string()),
+                result.duration_ms
+            )
+        };
+        let truncated = if result.truncated { "\n[output truncated]" } else { "" };
+        Ok(format!("$ {}\n{}{}\n[sandbox: {}]", command, result.output, truncated, status))
+    }
+
+    pub async fn send_signal(session_id: Option<&str>, signal: TerminalSignal) -> AppResult<String> {
+        let session_id = Self::resolve_session(session_id)?;
+        TERMINAL_MANAGER.lock().unwrap().signal_session(&session_id, signal)?;
+        Ok(format!("Signal {:?} sent to terminal", signal))
+    }
+
+    pub async fn get_recent_output(session_id: Option<&str>, lines: usize) -> AppResult<String> {
+        let lines = lines.min(MAX_RECENT_OUTPUT_LINES);
+        Self::with_session(session_id, |session| session.scrollback.last_lines(lines).join("\n"))
+    }
+
+    pub async fn get_last_command_output(session_id: Option<&str>) -> AppResult<String> {
+        Self::with_session(session_id, |session| match &session.last_command {
+            Some(last) => format!(
+                "$ {}\n{}\n[exit code: {}]",
+                last.command,
+                last.output,
+                last.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "unknown".to_string())
+            ),
+            None => "No completed command yet".to_string(),
+        })
+    }
+
+    pub async fn read_screen(session_id: Option<&str>) -> AppResult<String> {
+        Self::with_session(session_id, |session| session.screen.text())
+    }
+
+    pub async fn get_current_directory(session_id: Option<&str>) -> AppResult<String> {
+        Self::with_session(session_id, |session| session.current_dir())?
+            .ok_or_else(|| AppError::pty_io("Current directory is unknown"))
+    }
+
+    pub async fn list_files(session_id: Option<&str>) -> AppResult<String> {
+        let current_dir = Self::get_current_directory(session_id).await?;
         
         match std::fs::read_dir(&current_dir) {
             Ok(entries) => {
@@ -117,160 +524,285 @@ impl TerminalMCPServer {
                     .collect();
                 Ok(format!("Files in {}:\n{}", current_dir, files.join("\n")))
             }
-            Err(e) => Err(format!("Failed to read directory: {}", e))
+            Err(e) => Err(AppError::io(format!("Failed to read directory: {}", e)))
+        }
+    }
+
+    pub async fn list_sessions() -> AppResult<String> {
+        let manager = TERMINAL_MANAGER.lock().unwrap();
+        let active = manager.get_active_session();
+        let sessions: Vec<serde_json::Value> = manager.sessions()
+            .map(|session| serde_json::json!({
+                "id": session.id,
+                "shell": session.config.shell,
+                "cwd": session.current_dir(),
+                "running_command": session.running_command.as_ref().map(|c| c.command.clone()),
+                "active": active == Some(&session.id),
+            }))
+            .collect();
+        serde_json::to_string_pretty(&sessions).map_err(|e| AppError::from(e.to_string()))
+    }
+
+    // 未指定会话时使用活动终端
+    fn resolve_session(session_id: Option<&str>) -> AppResult<String> {
+        let manager = TERMINAL_MANAGER.lock().unwrap();
+        match session_id {
+            Some(id) if manager.get_session(id).is_some() => Ok(id.to_string()),
+            Some(id) => Err(AppError::session_not_found(id)),
+            None => manager.get_active_session().cloned()
+                .ok_or(AppError::NoActiveSession),
         }
     }
+
+    fn with_session<T>(session_id: Option<&str>, f: impl FnOnce(&TerminalSession) -> T) -> AppResult<T> {
+        let session_id = Self::resolve_session(session_id)?;
+        let manager = TERMINAL_MANAGER.lock().unwrap();
+        manager.get_session(&session_id)
+            .map(f)
+            .ok_or_else(|| AppError::session_not_found(&session_id))
+    }
 }
 
 // AI Agent
+#[derive(Clone)]
 pub struct AIAgent {
     config: AIConfig,
-    client: reqwest::Client,
+    provider: Arc<dyn ChatProvider>,
+    // 来源配置名，新建的对话会绑定到该配置
+    profile: Option<String>,
 }
 
 impl AIAgent {
-    pub fn new(config: AIConfig) -> Self {
+    pub fn with_profile(config: AIConfig, profile: Option<String>) -> Self {
         Self {
+            provider: providers::create_provider(&config),
             config,
-            client: reqwest::Client::new(),
+            profile,
         }
     }
 
-    pub async fn chat(&self, user_message: &str) -> Result<String, String> {
-        if self.config.api_key.is_empty() {
-            return Err("API key not configured".to_string());
-        }
+    pub async fn chat(&self, conversation_id: Option<&str>, user_message: &str) -> AppResult<ChatReply> {
+        self.run_chat(conversation_id, user_message, None).await
+    }
 
-        // 构建系统提示词，包含MCP功能说明
-        let system_prompt = r#"你是一个智能终端助手，可以帮助用户执行终端命令。你可以：
+    // 流式对话：通过 ai-chat-delta 事件推送增量内容
+    pub async fn chat_stream(&self, conversation_id: Option<&str>, user_message: &str, stream: &ChatStream) -> AppResult<ChatReply> {
+        self.run_chat(conversation_id, user_message, Some(stream)).await
+    }
 
-1. 理解用户的自然语言请求
-2. 将其转换为合适的终端命令
-3. 执行命令并返回结果
-4. 回答的内容需要使用Markdown格式
+    async fn run_chat(&self, conversation_id: Option<&str>, user_message: &str, stream: Option<&ChatStream>) -> AppResult<ChatReply> {
+        if self.config.api_key.is_empty() && self.provider.requires_api_key() {
+            return Err(AppError::AiNotConfigured { message: "API key not configured".to_string() });
+        }
+        println!("[RUST] Chat via {} provider, model {}", self.provider.name(), self.config.model);
 
-可用的MCP功能：
-- execute_command(command): 在终端中执行命令
-- get_current_directory(): 获取当前工作目录
-- list_files(): 列出当前目录的文件
+        let mut conversation = open_conversation(conversation_id, user_message, self.profile.as_deref())?;
+        self.compact_conversation(&mut conversation, user_message).await;
 
-请根据用户的需求，选择合适的命令执行。如果用户只是想查看信息，直接回答；如果需要执行命令，使用相应的MCP功能。"#;
+        let mut messages = vec![ChatMessage::new("system", SYSTEM_PROMPT)];
+        if let Some(summary) = &conversation.summary {
+            messages.push(ChatMessage::new("system", &format!("以下是之前对话的摘要：\n{}", summary)));
+        }
+        messages.extend_from_slice(conversation.context_messages());
+        let history_len = messages.len();
+        messages.push(ChatMessage::new("user", user_message));
+
+        let tools = available_tools();
+
+        // 循环执行模型请求的工具调用，直到模型给出最终回答
+        for _ in 0..MAX_TOOL_ROUNDS {
+            let message = match stream {
+                Some(stream) => tokio::select! {
+                    message = self.provider.stream(&messages, Some(&tools), stream) => message?,
+                    _ = stream.cancel.cancelled() => return Err(AppError::Cancelled),
+                },
+                None => self.provider.complete(&messages, Some(&tools)).await?,
+            };
+
+            let tool_calls = message.tool_calls.clone().unwrap_or_default();
+            if tool_calls.is_empty() {
+                let content = message.content.clone().unwrap_or_default();
+                println!("[RUST] AI response content: {}", content);
+                messages.push(message);
+                // 只有完整结束的一轮才写入历史，避免留下没有结果的工具调用
+                append_to_conversation(&conversation.id, messages.split_off(history_len));
+                return Ok(ChatReply {
+                    conversation_id: conversation.id,
+                    content,
+                });
+            }
 
-        let messages = vec![
-            ChatMessage {
-                role: "system".to_string(),
-                content: system_prompt.to_string(),
-            },
-            ChatMessage {
-                role: "user".to_string(),
-                content: user_message.to_string(),
-            },
-        ];
+            messages.push(message);
+            for call in tool_calls {
+                println!("[RUST] Executing tool {} with arguments {}", call.function.name, call.function.arguments);
+                let started_at = std::time::Instant::now();
+                let call_future = self.call_tool(&call, &conversation.id, stream);
+                let result = match stream {
+                    Some(stream) => tokio::select! {
+                        result = call_future => result,
+                        _ = stream.cancel.cancelled() => return Err(AppError::Cancelled),
+                    },
+                    None => call_future.await,
+                };
+                println!("[RUST] {} result: {:?}", call.function.name, result);
+                let success = result.is_ok();
+                let content = match result {
+                    Ok(output) => output,
+                    Err(e) => format!("Error: {}", e),
+                };
+                storage::record_tool_invocation(ToolInvocation {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    conversation_id: conversation.id.clone(),
+                    tool_call_id: call.id.clone(),
+                    name: call.function.name.clone(),
+                    arguments: call.function.arguments.clone(),
+                    result: content.clone(),
+                    success,
+                    duration_ms: started_at.elapsed().as_millis() as u64,
+                    timestamp: conversation::now_millis(),
+                });
+                messages.push(ChatMessage::tool_result(&call.id, &content));
+            }
+        }
+
+        Err(AppError::from(format!("AI did not produce an answer after {} tool rounds", MAX_TOOL_ROUNDS)))
+    }
 
-        let request = ChatRequest {
-            model: self.config.model.clone(),
-            messages,
-            max_tokens: self.config.max_tokens,
-            temperature: self.config.temperature,
-            stream: false,
+    // 执行命令或调用外部 MCP 工具前按当前配置的审批规则检查，需要时等待用户在界面上确认
+    async fn call_tool(&self, call: &ToolCall, conversation_id: &str, stream: Option<&ChatStream>) -> AppResult<String> {
+        let proposed = if mcp_client::is_external_tool(&call.function.name) {
+            mcp_client::proposed_call(&call.function.name, &call.function.arguments)
+        } else {
+            TerminalMCPServer::proposed_command(&call.function.name, &call.function.arguments)
         };
+        if let Some((command, classification)) = proposed {
+            let context = ApprovalContext {
+                app_handle: stream.map(|s| &s.app_handle),
+                request_id: stream.map(|s| s.request_id.as_str()),
+                conversation_id,
+            };
+            policy::authorize(&self.config.approval, &command, classification, context).await?;
+        }
+        if mcp_client::is_external_tool(&call.function.name) {
+            return mcp_client::call_tool(&call.function.name, &call.function.arguments).await;
+        }
+        TerminalMCPServer::call_tool(&call.function.name, &call.function.arguments).await
+    }
 
-        let url = format!("{}/chat/completions", self.config.base_url);
-        println!("[RUST] Making request to: {}", url);
-        println!("[RUST] Request body: {}", serde_json::to_string_pretty(&request).unwrap());
-
-        let response = self.client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
-
-        let status = response.status();
-        println!("[RUST] Response status: {}", status);
-        
-        let response_text = response.text().await.map_err(|e| format!("Failed to get response text: {}", e))?;
-        println!("[RUST] Raw response: {}", response_text);
-
-        // 检查响应状态码
-        if !status.is_success() {
-            // 尝试解析错误响应
-            match serde_json::from_str::<ErrorResponse>(&response_text) {
-                Ok(error_response) => {
-                    println!("[RUST] API Error: {}", error_response.error.message);
-                    return Err(error_response.error.message);
-                }
-                Err(_) => {
-                    // 如果无法解析错误响应，返回原始响应
-                    return Err(format!("API Error ({}): {}", status, response_text));
-                }
-            }
+    // 历史超出上下文窗口时，把较早的若干轮对话压缩成摘要
+    async fn compact_conversation(&self, conversation: &mut Conversation, user_message: &str) {
+        let reserved = self.config.max_tokens as usize
+            + conversation::estimate_message_tokens(&ChatMessage::new("system", SYSTEM_PROMPT))
+            + conversation::estimate_message_tokens(&ChatMessage::new("user", user_message))
+            + serde_json::to_string(&available_tools()).map(|s| s.len() / 4).unwrap_or(0);
+        let budget = (self.config.context_window as usize).saturating_sub(reserved);
+
+        let summary_tokens = conversation.summary.as_ref()
+            .map(|s| conversation::estimate_message_tokens(&ChatMessage::new("system", s)))
+            .unwrap_or(0);
+        let history = conversation.context_messages();
+        if summary_tokens + conversation::estimate_tokens(history) <= budget {
+            return;
         }
 
-        let chat_response: ChatResponse = serde_json::from_str(&response_text)
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-        if let Some(choice) = chat_response.choices.first() {
-            let content = &choice.message.content;
-            println!("[RUST] AI response content: {}", content);
-            
-            // 检查是否需要执行MCP命令
-            if content.contains("list_files") || content.contains("get_current_directory") || content.contains("execute_command") {
-                println!("[RUST] Detected MCP command in response, handling...");
-                return self.handle_mcp_commands(content).await;
+        // 只在用户消息处切分，保证工具调用和结果不会被拆开
+        let target = budget / COMPACT_TARGET_RATIO;
+        let mut cut = history.len();
+        for (index, message) in history.iter().enumerate().skip(1) {
+            if message.role == "user" && conversation::estimate_tokens(&history[index..]) <= target {
+                cut = index;
+                break;
             }
-            
-            Ok(content.clone())
-        } else {
-            Err("No response from AI".to_string())
+        }
+        let dropped = &history[..cut];
+        println!("[RUST] Compacting conversation {}: summarizing {} messages", conversation.id, dropped.len());
+
+        let summary = match self.summarize(conversation.summary.as_deref(), dropped).await {
+            Ok(summary) => Some(summary),
+            Err(e) => {
+                // 摘要失败时直接丢弃较早的消息，保留原有摘要
+                eprintln!("Failed to summarize conversation: {}", e);
+                conversation.summary.clone()
+            }
+        };
+
+        conversation.summary = summary;
+        conversation.summarized_upto += cut;
+        if let Some(stored) = CONVERSATIONS.lock().unwrap().get_mut(&conversation.id) {
+            stored.summary = conversation.summary.clone();
+            stored.summarized_upto = conversation.summarized_upto;
+            storage::save_conversation(stored);
         }
     }
 
-    async fn handle_mcp_commands(&self, content: &str) -> Result<String, String> {
-        println!("[RUST] Handling MCP commands in content: {}", content);
-        
-        // 简单的MCP命令解析
-        if content.contains("list_files") {
-            println!("[RUST] Executing list_files command");
-            let result = TerminalMCPServer::list_files().await;
-            println!("[RUST] list_files result: {:?}", result);
-            result
-        } else if content.contains("get_current_directory") {
-            println!("[RUST] Executing get_current_directory command");
-            let result = TerminalMCPServer::get_current_directory().await;
-            println!("[RUST] get_current_directory result: {:?}", result);
-            result
-        } else if content.contains("execute_command") {
-            println!("[RUST] Executing execute_command");
-            // 提取命令内容
-            if let Some(command) = self.extract_command(content) {
-                println!("[RUST] Extracted command: {}", command);
-                let result = TerminalMCPServer::execute_command(&command).await;
-                println!("[RUST] execute_command result: {:?}", result);
-                result
-                    } else {
-                println!("[RUST] No command found to execute");
-                Ok("No command found to execute".to_string())
+    async fn summarize(&self, previous: Option<&str>, messages: &[ChatMessage]) -> AppResult<String> {
+        let mut transcript = String::new();
+        if let Some(previous) = previous {
+            transcript.push_str(&format!("[之前的摘要]\n{}\n\n", previous));
+        }
+        for message in messages {
+            if let Some(content) = &message.content {
+                let content: String = content.chars().take(SUMMARY_MESSAGE_MAX_CHARS).collect();
+                transcript.push_str(&format!("{}: {}\n", message.role, content));
+            }
+            for call in message.tool_calls.iter().flatten() {
+                transcript.push_str(&format!("assistant 调用工具 {}({})\n", call.function.name, call.function.arguments));
             }
-        } else {
-            println!("[RUST] No MCP command detected, returning original content");
-            Ok(content.to_string())
         }
+
+        let request = vec![
+            ChatMessage::new("system", SUMMARY_PROMPT),
+            ChatMessage::new("user", &transcript),
+        ];
+        let message = self.provider.complete(&request, None).await?;
+        message.content
+            .filter(|content| !content.trim().is_empty())
+            .ok_or_else(|| AppError::from("Empty summary"))
     }
+}
 
-    fn extract_command(&self, content: &str) -> Option<String> {
-        // 简单的命令提取逻辑
-        if let Some(start) = content.find("execute_command(") {
-            if let Some(end) = content[start..].find(')') {
-                let command_content = &content[start + 16..start + end];
-                // 移除引号
-                let command = command_content.trim_matches('"').trim_matches('\'');
-                return Some(command.to_string());
-            }
+// 系统提示词
+const SYSTEM_PROMPT: &str = r#"你是一个智能终端助手，可以帮助用户执行终端命令。你可以：
+
+1. 理解用户的自然语言请求
+2. 将其转换为合适的终端命令
+3. 通过工具调用执行命令并查看结果
+4. 回答的内容需要使用Markdown格式
+
+你可以调用的工具包括：在终端中执行命令、获取当前工作目录、列出文件、读取终端屏幕和最近的输出、向前台进程发送信号。名称以 mcp__ 开头的工具来自用户配置的外部 MCP 服务。
+
+请根据用户的需求选择合适的工具。如果用户只是想了解信息，直接回答；如果需要执行命令，调用 execute_command 并根据返回的输出和退出码给出结论。"#;
+
+// 压缩历史时使用的提示词
+const SUMMARY_PROMPT: &str = "请将下面的终端助手对话压缩成一段简洁的摘要，保留用户的目标、执行过的关键命令及其结果、当前工作目录和尚未完成的事项。只输出摘要本身。";
+
+// 内置终端工具加上已连接的外部 MCP 服务提供的工具
+fn available_tools() -> Vec<Tool> {
+    let mut tools = TerminalMCPServer::tools();
+    tools.extend(mcp_client::tools());
+    tools
+}
+
+// 取出已有对话，或以本条消息为标题新建一个
+fn open_conversation(conversation_id: Option<&str>, user_message: &str, profile: Option<&str>) -> AppResult<Conversation> {
+    let mut store = CONVERSATIONS.lock().unwrap();
+    match conversation_id {
+        Some(id) => store.get(id).cloned().ok_or_else(|| AppError::not_found("Conversation", id)),
+        None => {
+            let mut conversation = Conversation::new(&conversation::title_from_message(user_message));
+            conversation.profile = profile.map(|p| p.to_string());
+            store.insert(conversation.clone());
+            Ok(conversation)
         }
-        None
+    }
+}
+
+fn append_to_conversation(conversation_id: &str, messages: Vec<ChatMessage>) {
+    let mut store = CONVERSATIONS.lock().unwrap();
+    if let Some(conversation) = store.get_mut(conversation_id) {
+        conversation.messages.extend(messages);
+        conversation.updated_at = conversation::now_millis();
+        storage::save_conversation(conversation);
     }
 }
 
@@ -284,30 +816,106 @@ pub static AI_AGENT: Lazy<Arc<Mutex<Option<AIAgent>>>> =
 
 // Tauri 命令
 #[tauri::command]
-pub async fn configure_ai(config: AIConfig) -> Result<(), String> {
-    let agent = AIAgent::new(config);
-    let mut global_agent = AI_AGENT.lock().await;
-    *global_agent = Some(agent);
-    Ok(())
+pub async fn configure_ai(config: AIConfig) -> AppResult<()> {
+    // 写入当前配置并持久化，下次启动无需重新发送
+    profiles::save_active_config(config).await
 }
 
 #[tauri::command]
-pub async fn chat_with_ai(message: String) -> Result<String, String> {
-    let agent_guard = AI_AGENT.lock().await;
-    
-    if let Some(agent) = &*agent_guard {
-        agent.chat(&message).await
-    } else {
-        Err("AI Agent not configured. Please configure API key first.".to_string())
+pub async fn chat_with_ai(message: String, conversation_id: Option<String>, profile: Option<String>) -> AppResult<ChatReply> {
+    let agent = resolve_agent(conversation_id.as_deref(), profile).await?;
+    agent.chat(conversation_id.as_deref(), &message).await
+}
+
+// 流式对话，结束时发送 ai-chat-done 或 ai-chat-error 事件
+#[tauri::command]
+pub async fn chat_with_ai_stream(
+    app_handle: AppHandle,
+    request_id: String,
+    message: String,
+    conversation_id: Option<String>,
+    profile: Option<String>,
+) -> AppResult<ChatReply> {
+    let agent = resolve_agent(conversation_id.as_deref(), profile).await?;
+
+    let cancel = CancellationToken::new();
+    ACTIVE_CHATS.lock().unwrap().insert(request_id.clone(), cancel.clone());
+    let stream = ChatStream {
+        app_handle: app_handle.clone(),
+        request_id: request_id.clone(),
+        cancel,
+        usage: std::sync::Mutex::new(Usage::default()),
+    };
+
+    let result = agent.chat_stream(conversation_id.as_deref(), &message, &stream).await;
+    ACTIVE_CHATS.lock().unwrap().remove(&request_id);
+
+    let emitted = match &result {
+        Ok(reply) => app_handle.emit("ai-chat-done", &ChatDoneEvent {
+            request_id,
+            conversation_id: reply.conversation_id.clone(),
+            content: reply.content.clone(),
+            usage: stream.usage.lock().unwrap().clone(),
+        }),
+        Err(error) => app_handle.emit("ai-chat-error", &ChatErrorEvent {
+            request_id,
+            error: error.clone(),
+            cancelled: matches!(error, AppError::Cancelled),
+        }),
+    };
+    if let Err(e) = emitted {
+        eprintln!("Failed to emit chat result: {}", e);
     }
+    result
+}
+
+#[tauri::command]
+pub async fn cancel_chat(request_id: String) -> AppResult<()> {
+    match ACTIVE_CHATS.lock().unwrap().get(&request_id) {
+        Some(token) => {
+            token.cancel();
+            Ok(())
+        }
+        None => Err(AppError::not_found("Chat request", &request_id)),
+    }
+}
+
+// 选择使用的配置：显式指定的 > 对话绑定的 > 当前激活的
+async fn resolve_agent(conversation_id: Option<&str>, profile: Option<String>) -> AppResult<AIAgent> {
+    // 显式指定的配置先校验再绑定到会话
+    if let Some(name) = profile {
+        let config = profiles::get_profile_config(&name)?;
+        if let Some(id) = conversation_id {
+            conversation::set_profile(id, Some(&name))?;
+        }
+        return Ok(AIAgent::with_profile(config, Some(name)));
+    }
+
+    // 会话绑定的配置可能已被删除，此时回退到当前配置
+    let bound = conversation_id.and_then(|id| CONVERSATIONS.lock().unwrap().get(id).and_then(|c| c.profile.clone()));
+    if let Some(name) = bound {
+        match profiles::get_profile_config(&name) {
+            Ok(config) => return Ok(AIAgent::with_profile(config, Some(name))),
+            Err(_) => println!("[RUST] Profile {} no longer exists, using the active profile", name),
+        }
+    }
+    current_agent().await
+}
+
+async fn current_agent() -> AppResult<AIAgent> {
+    let agent_guard = AI_AGENT.lock().await;
+    agent_guard.clone()
+        .ok_or_else(|| AppError::AiNotConfigured {
+            message: "AI Agent not configured. Please configure API key first.".to_string(),
+        })
 }
 
 #[tauri::command]
-pub async fn get_ai_config() -> Result<Option<AIConfig>, String> {
+pub async fn get_ai_config() -> AppResult<Option<RedactedAIConfig>> {
     let agent_guard = AI_AGENT.lock().await;
     
     if let Some(agent) = &*agent_guard {
-        Ok(Some(agent.config.clone()))
+        Ok(Some(RedactedAIConfig::from(&agent.config)))
     } else {
         Ok(None)
     }
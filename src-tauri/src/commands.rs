@@ -7,6 +7,24 @@ use once_cell::sync::Lazy;
 use std::io::{Read};
 use std::os::unix::io::AsRawFd;
 use std::io::Error;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::shell_integration::{self, OscParser, ShellEvent, ShellOutput};
+use crate::scrollback::{ScrollbackBuffer, ScrollbackRange, DEFAULT_SCROLLBACK_BYTES, DEFAULT_SCROLLBACK_LINES};
+use crate::screen::{self, ScreenSnapshot, VirtualScreen, DEFAULT_SCREEN_SCROLLBACK};
+use crate::storage::{self, CommandRecord};
+use crate::plugins::{doge_args, PluginDispatcher, PluginEvent, PLUGIN_REGISTRY};
+use crate::error::{AppError, AppResult};
+use async_trait::async_trait;
+use tokio::sync::oneshot;
+
+// 子进程状态轮询间隔
+const EXIT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+// execute_and_wait 的默认超时时间
+pub const DEFAULT_EXECUTE_TIMEOUT_MS: u64 = 30_000;
+
+// 关闭会话时等待进程退出的时间，超时后发送 SIGKILL
+const CLOSE_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(3);
 
 // 全局终端管理器
 pub static TERMINAL_MANAGER: Lazy<Arc<Mutex<TerminalManager>>> = 
@@ -19,6 +37,33 @@ pub struct TerminalConfig {
     pub working_dir: Option<String>,
     pub columns: u16,
     pub rows: u16,
+    #[serde(default = "default_shell_integration")]
+    pub shell_integration: bool,
+    #[serde(default = "default_scrollback_lines")]
+    pub scrollback_lines: usize,
+    #[serde(default = "default_scrollback_bytes")]
+    pub scrollback_bytes: usize,
+    #[serde(default = "default_screen_scrollback")]
+    pub screen_scrollback: usize,
+    // 要加载的插件，为空时使用插件设置中启用的插件
+    #[serde(default)]
+    pub plugins: Option<Vec<String>>,
+}
+
+fn default_shell_integration() -> bool {
+    true
+}
+
+fn default_scrollback_lines() -> usize {
+    DEFAULT_SCROLLBACK_LINES
+}
+
+fn default_scrollback_bytes() -> usize {
+    DEFAULT_SCROLLBACK_BYTES
+}
+
+fn default_screen_scrollback() -> usize {
+    DEFAULT_SCREEN_SCROLLBACK
 }
 
 impl Default for TerminalConfig {
@@ -35,6 +80,63 @@ impl Default for TerminalConfig {
             working_dir: std::env::current_dir().ok().map(|p| p.to_string_lossy().to_string()),
             columns: 80,
             rows: 24,
+            shell_integration: default_shell_integration(),
+            scrollback_lines: default_scrollback_lines(),
+            scrollback_bytes: default_scrollback_bytes(),
+            screen_scrollback: default_screen_scrollback(),
+            plugins: None,
+        }
+    }
+}
+
+// 终端输出事件，携带会话ID以便前端区分多个标签页
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalOutputEvent {
+    pub session_id: String,
+    pub data: String,
+}
+
+// 会话中的 shell 进程退出事件
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalExitEvent {
+    pub session_id: String,
+    pub exit_code: u32,
+    pub success: bool,
+    pub signal: Option<String>,
+}
+
+// 会话工作目录变化事件
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalCwdEvent {
+    pub session_id: String,
+    pub cwd: String,
+}
+
+// 可发送给会话的信号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TerminalSignal {
+    Sigint,
+    Sigtstp,
+    Sigquit,
+    Sigwinch,
+    Sigcont,
+    Sighup,
+    Sigterm,
+    Sigkill,
+}
+
+impl TerminalSignal {
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            TerminalSignal::Sigint => libc::SIGINT,
+            TerminalSignal::Sigtstp => libc::SIGTSTP,
+            TerminalSignal::Sigquit => libc::SIGQUIT,
+            TerminalSignal::Sigwinch => libc::SIGWINCH,
+            TerminalSignal::Sigcont => libc::SIGCONT,
+            TerminalSignal::Sighup => libc::SIGHUP,
+            TerminalSignal::Sigterm => libc::SIGTERM,
+            TerminalSignal::Sigkill => libc::SIGKILL,
         }
     }
 }
@@ -43,21 +145,423 @@ impl Default for TerminalConfig {
 pub struct TerminalSession {
     pub id: String,
     pub pty: Arc<Mutex<Box<dyn portable_pty::MasterPty + Send>>>,
+    pub child: Arc<Mutex<Box<dyn portable_pty::Child + Send + Sync>>>,
+    pub pid: Option<u32>,
     pub config: TerminalConfig,
-    pub plugins: Vec<Box<dyn TerminalPlugin + Send + Sync>>,
+    pub plugins: PluginDispatcher,
     pub app_handle: AppHandle,
+    pub osc_parser: OscParser,
+    // 是否已收到 shell 集成标记，收到后命令边界由 shell 上报
+    pub integration_active: bool,
+    pub pending_command: Option<String>,
+    pub running_command: Option<RunningCommand>,
+    // 最近一次 OSC 7 上报的工作目录
+    pub cwd: Option<String>,
+    pub scrollback: ScrollbackBuffer,
+    // 当前命令的输出，命令结束后转存到 last_command
+    pub command_capture: Option<String>,
+    pub last_command: Option<CommandOutput>,
+    // 虚拟终端状态，用于生成纯文本屏幕快照
+    pub screen: VirtualScreen,
+    // 等待下一条命令结束的调用方
+    pub command_waiters: Vec<oneshot::Sender<CommandOutput>>,
+    // 用户在提示符下输入的命令行，出现方向键、Tab 等无法跟踪的编辑时为 None
+    pub input_line: Option<String>,
+    // 插件对拦截命令的回复，等下一个提示符出现时写到终端
+    pub pending_response: Option<String>,
+    // 等待插件处理命令行期间，后续输入需要排队
+    pub input_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+// 已完成命令的输出
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandOutput {
+    pub command: String,
+    pub output: String,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    // 输出在 scrollback 中的行偏移范围
+    pub start_offset: u64,
+    pub end_offset: u64,
 }
 
-// 插件系统接口
+// execute_and_wait 的返回结果，output 为渲染后的纯文本
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecuteResult {
+    pub command: String,
+    pub output: String,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub timed_out: bool,
+    // 超时后是否向前台命令发送了 SIGINT
+    pub interrupted: bool,
+}
+
+// 正在执行的命令
+#[derive(Debug, Clone)]
+pub struct RunningCommand {
+    pub command: String,
+    pub started_at: Instant,
+    pub start_offset: u64,
+    pub cwd: Option<String>,
+    // 毫秒时间戳，用于持久化的历史记录
+    pub started_at_ms: u64,
+}
+
+impl TerminalSession {
+    // 当前占用终端的前台进程组，没有时退回到 shell 自身的进程组
+    pub fn foreground_pgid(&self) -> Option<libc::pid_t> {
+        let leader = self.pty.lock().unwrap().process_group_leader();
+        leader
+            .filter(|pgid| *pgid > 0)
+            .or_else(|| self.pid.map(|pid| pid as libc::pid_t))
+    }
+
+    pub fn send_signal(&self, signal: TerminalSignal) -> AppResult<()> {
+        let pgid = self.foreground_pgid()
+            .ok_or_else(|| AppError::pty_io("Shell process id unavailable"))?;
+        signal_process_group(pgid, signal.as_raw())
+    }
+
+    // 向前台命令发送 SIGINT，前台是 shell 自身时不发送
+    pub fn interrupt_foreground(&self) -> bool {
+        let shell = self.pid.map(|pid| pid as libc::pid_t);
+        let leader = self.pty.lock().unwrap().process_group_leader().filter(|pgid| *pgid > 0);
+        match leader {
+            Some(pgid) if Some(pgid) != shell => match signal_process_group(pgid, libc::SIGINT) {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Failed to interrupt command in session {}: {}", self.id, e);
+                    false
+                }
+            },
+            _ => false,
+        }
+    }
+
+    // 实时工作目录：命令执行中优先读取前台进程，否则使用 OSC 7 上报的目录
+    pub fn current_dir(&self) -> Option<String> {
+        if self.running_command.is_some() || self.cwd.is_none() {
+            if let Some(dir) = self.foreground_pgid().and_then(process_cwd) {
+                return Some(dir);
+            }
+        }
+        self.cwd.clone().or_else(|| self.config.working_dir.clone())
+    }
+
+    // 解析 shell 集成标记，返回要发送到前端的输出；插件只收到副本，不阻塞输出
+    pub fn process_output(&mut self, output: &str) -> String {
+        let mut processed = String::new();
+        for item in self.osc_parser.feed(output) {
+            match item {
+                ShellOutput::Text(text) => {
+                    self.plugins.send(PluginEvent::Output(text.clone()));
+                    self.capture_output(&text);
+                    processed.push_str(&text);
+                }
+                ShellOutput::Event(event) => {
+                    // 插件回复放在新提示符之前，保证出现在 shell 清除命令行之后
+                    if matches!(event, ShellEvent::PromptStart) {
+                        if let Some(response) = self.pending_response.take() {
+                            self.capture_output(&response);
+                            processed.push_str(&response);
+                        }
+                    }
+                    self.handle_shell_event(event);
+                }
+            }
+        }
+        processed
+    }
+
+    fn handle_shell_event(&mut self, event: ShellEvent) {
+        self.integration_active = true;
+        match event {
+            ShellEvent::CommandLine(command) => {
+                self.pending_command = Some(command);
+            }
+            ShellEvent::CommandExecuted => {
+                let command = self.pending_command.take().unwrap_or_default();
+                self.notify_command_start(&command);
+                self.running_command = Some(RunningCommand {
+                    command,
+                    started_at: Instant::now(),
+                    start_offset: self.scrollback.end_offset(),
+                    cwd: self.cwd.clone().or_else(|| self.config.working_dir.clone()),
+                    started_at_ms: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_millis() as u64)
+                        .unwrap_or(0),
+                });
+                self.command_capture = Some(String::new());
+            }
+            ShellEvent::CommandFinished(exit_code) => {
+                if let Some(running) = self.running_command.take() {
+                    let output = CommandOutput {
+                        command: running.command,
+                        output: self.command_capture.take().unwrap_or_default(),
+                        exit_code,
+                        duration_ms: running.started_at.elapsed().as_millis() as u64,
+                        start_offset: running.start_offset,
+                        end_offset: self.scrollback.end_offset(),
+                    };
+                    for waiter in self.command_waiters.drain(..) {
+                        let _ = waiter.send(output.clone());
+                    }
+                    storage::record_command(CommandRecord {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        session_id: self.id.clone(),
+                        command: output.command.clone(),
+                        cwd: running.cwd,
+                        exit_code,
+                        duration_ms: output.duration_ms,
+                        started_at: running.started_at_ms,
+                        output: output.output.clone(),
+                    });
+                    self.last_command = Some(output);
+                    self.plugins.send(PluginEvent::CommandEnd(exit_code));
+                }
+            }
+            ShellEvent::Cwd(cwd) => {
+                if self.cwd.as_ref() != Some(&cwd) {
+                    let event = TerminalCwdEvent {
+                        session_id: self.id.clone(),
+                        cwd: cwd.clone(),
+                    };
+                    if let Err(e) = self.app_handle.emit("terminal-cwd", &event) {
+                        eprintln!("Failed to emit terminal cwd: {}", e);
+                    }
+                    self.plugins.send(PluginEvent::CwdChange(cwd.clone()));
+                    self.cwd = Some(cwd);
+                }
+            }
+            ShellEvent::PromptStart | ShellEvent::CommandInputStart => {
+                self.input_line = Some(String::new());
+            }
+        }
+    }
+
+    fn capture_output(&mut self, text: &str) {
+        self.scrollback.push(text);
+        self.screen.process(text);
+
+        if let Some(capture) = self.command_capture.as_mut() {
+            capture.push_str(text);
+            // 单条命令的输出同样受 scrollback 字节上限约束，只保留末尾
+            let max_bytes = self.config.scrollback_bytes;
+            if capture.len() > max_bytes {
+                let mut cut = capture.len() - max_bytes;
+                while !capture.is_char_boundary(cut) {
+                    cut += 1;
+                }
+                capture.drain(..cut);
+            }
+        }
+    }
+
+    fn notify_command_start(&mut self, command: &str) {
+        self.plugins.send(PluginEvent::CommandStart(command.to_string()));
+    }
+
+    // shell 正在等待输入命令，而不是有程序在读取输入
+    fn at_prompt(&self) -> bool {
+        self.integration_active && self.running_command.is_none()
+    }
+
+    // 应用插件对命令的处理结果，返回要执行的命令；插件已处理时返回 None
+    fn apply_command_action(&mut self, command: &str, action: CommandAction) -> Option<String> {
+        let response = match action {
+            CommandAction::Continue => return Some(command.to_string()),
+            CommandAction::Rewrite(rewritten) => return Some(rewritten),
+            CommandAction::Swallow => String::new(),
+            CommandAction::Respond(text) => text,
+        };
+
+        // 按终端输出处理，统一换行为 \r\n
+        let mut text = response.replace("\r\n", "\n").replace('\n', "\r\n");
+        if !text.is_empty() && !text.ends_with("\r\n") {
+            text.push_str("\r\n");
+        }
+
+        // 等待该命令结束的调用方直接拿到插件的回复
+        let output = CommandOutput {
+            command: command.to_string(),
+            output: text.clone(),
+            exit_code: Some(0),
+            duration_ms: 0,
+            start_offset: self.scrollback.end_offset(),
+            end_offset: self.scrollback.end_offset(),
+        };
+        for waiter in self.command_waiters.drain(..) {
+            let _ = waiter.send(output.clone());
+        }
+
+        if !text.is_empty() {
+            if self.integration_active {
+                self.pending_response = Some(text);
+            } else {
+                self.emit_output(&format!("\r\n{}", text));
+            }
+        }
+        None
+    }
+
+    // 不经过 PTY 直接写到前端终端
+    fn emit_output(&mut self, text: &str) {
+        self.capture_output(text);
+        let event = TerminalOutputEvent {
+            session_id: self.id.clone(),
+            data: text.to_string(),
+        };
+        if let Err(e) = self.app_handle.emit("terminal-output", &event) {
+            eprintln!("Failed to emit terminal output: {}", e);
+        }
+    }
+
+    // 跟踪用户在提示符下输入的命令行，返回可以直接发送给 shell 的内容；
+    // 遇到需要插件处理的回车时停下，同时返回该命令行和已处理的字节数
+    fn process_input(&mut self, data: &str) -> (String, Option<(String, usize)>) {
+        let mut forward = String::new();
+        for (index, ch) in data.char_indices() {
+            match ch {
+                '\r' => {
+                    let line = self.input_line.replace(String::new());
+                    match line.filter(|line| !line.trim().is_empty() && self.at_prompt()) {
+                        Some(line) => return (forward, Some((line, index + ch.len_utf8()))),
+                        None => forward.push('\r'),
+                    }
+                }
+                '\x7f' | '\x08' => {
+                    if let Some(line) = self.input_line.as_mut() {
+                        line.pop();
+                    }
+                    forward.push(ch);
+                }
+                // Ctrl-C 和 Ctrl-U 都会清空当前命令行
+                '\x03' | '\x15' => {
+                    self.input_line = Some(String::new());
+                    forward.push(ch);
+                }
+                c if c.is_control() => {
+                    self.input_line = None;
+                    forward.push(c);
+                }
+                c => {
+                    if let Some(line) = self.input_line.as_mut() {
+                        line.push(c);
+                    }
+                    forward.push(c);
+                }
+            }
+        }
+        (forward, None)
+    }
+
+    // 插件处理完用户输入的命令行后，返回代替回车发送给 shell 的内容
+    fn finish_input_command(&mut self, line: &str, action: CommandAction) -> String {
+        match self.apply_command_action(line, action) {
+            Some(command) if command == line => "\r".to_string(),
+            // 先用 Ctrl-U 清除已输入的内容再换成新命令
+            Some(command) => format!("\x15{}\r", command),
+            // 清除命令行后发送空行，让 shell 显示新的提示符
+            None => "\x15\r".to_string(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_cwd(pid: libc::pid_t) -> Option<String> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid))
+        .ok()
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_cwd(_pid: libc::pid_t) -> Option<String> {
+    None
+}
+
+fn signal_process_group(pgid: libc::pid_t, signal: libc::c_int) -> AppResult<()> {
+    let ret = unsafe { libc::kill(-pgid, signal) };
+    if ret < 0 {
+        let err = Error::last_os_error();
+        // 进程组已经不存在
+        if err.raw_os_error() == Some(libc::ESRCH) {
+            return Ok(());
+        }
+        return Err(AppError::pty_io(format!("Failed to send signal: {}", err)));
+    }
+    Ok(())
+}
+
+// 关闭会话：先 SIGHUP/SIGTERM，超时后 SIGKILL
+fn terminate_session(session: &TerminalSession) {
+    let shell_pgid = session.pid.map(|pid| pid as libc::pid_t);
+    let foreground_pgid = session.foreground_pgid();
+    let child = session.child.clone();
+
+    let signal_all = move |signal: libc::c_int| {
+        for pgid in [foreground_pgid, shell_pgid].into_iter().flatten() {
+            if let Err(e) = signal_process_group(pgid, signal) {
+                eprintln!("{}", e);
+            }
+        }
+    };
+
+    signal_all(libc::SIGHUP);
+    signal_all(libc::SIGTERM);
+
+    tokio::spawn(async move {
+        let deadline = tokio::time::Instant::now() + CLOSE_GRACE_PERIOD;
+        while tokio::time::Instant::now() < deadline {
+            if !matches!(child.lock().unwrap().try_wait(), Ok(None)) {
+                return;
+            }
+            tokio::time::sleep(EXIT_POLL_INTERVAL).await;
+        }
+
+        signal_all(libc::SIGKILL);
+        if let Err(e) = child.lock().unwrap().kill() {
+            eprintln!("Failed to kill shell process: {}", e);
+        }
+    });
+}
+
+// before_command 钩子的处理结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandAction {
+    // 不处理，交给下一个插件
+    Continue,
+    // 替换命令行，后面的插件看到的是替换后的命令
+    Rewrite(String),
+    // 丢弃命令，不发送给 shell
+    Swallow,
+    // 不发送给 shell，把插件生成的内容写回终端
+    Respond(String),
+}
+
+// 命令的来源。AI 提出的命令按原文审批，不接受插件改写
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    User,
+    Ai,
+}
+
+// 插件系统接口，钩子在会话的插件分发任务中调用，超时的调用会被取消
+#[async_trait]
 pub trait TerminalPlugin: Send + Sync {
     fn name(&self) -> &str;
-    fn on_command_start(&mut self, _command: &str, _session_id: &str) {}
-    fn on_output(&mut self, output: &str, _session_id: &str) -> String {
-        output.to_string() // 默认不处理
+    // 命令发送给 shell 之前调用，可以改写、丢弃命令或直接回复
+    async fn before_command(&mut self, _command: &str, _session_id: &str) -> CommandAction {
+        CommandAction::Continue
     }
-    fn on_command_end(&mut self, _exit_code: Option<i32>, _session_id: &str) {}
-    fn on_session_start(&mut self, _session_id: &str) {}
-    fn on_session_end(&mut self, _session_id: &str) {}
+    async fn on_command_start(&mut self, _command: &str, _session_id: &str) {}
+    // 只读的输出副本，插件处理不过来时会被丢弃
+    async fn on_output(&mut self, _output: &str, _session_id: &str) {}
+    async fn on_command_end(&mut self, _exit_code: Option<i32>, _session_id: &str) {}
+    async fn on_cwd_change(&mut self, _cwd: &str, _session_id: &str) {}
+    async fn on_session_start(&mut self, _session_id: &str) {}
+    async fn on_session_end(&mut self, _session_id: &str) {}
 }
 
 // 内置插件：命令历史
@@ -73,12 +577,25 @@ impl HistoryPlugin {
     }
 }
 
+#[async_trait]
 impl TerminalPlugin for HistoryPlugin {
     fn name(&self) -> &str {
         "history"
     }
 
-    fn on_command_start(&mut self, command: &str, _session_id: &str) {
+    // doge history 列出本会话的命令，doge history clear 清空
+    async fn before_command(&mut self, command: &str, _session_id: &str) -> CommandAction {
+        match doge_args(command, "history") {
+            Some("clear") => {
+                self.history.clear();
+                CommandAction::Swallow
+            }
+            Some(_) => CommandAction::Respond(format!("🐶 History:\n{}", self.history.join("\n"))),
+            None => CommandAction::Continue,
+        }
+    }
+
+    async fn on_command_start(&mut self, command: &str, _session_id: &str) {
         if !command.trim().is_empty() {
             self.history.push(command.to_string());
         }
@@ -92,11 +609,6 @@ impl TerminalPlugin for ColorPlugin {
     fn name(&self) -> &str {
         "color"
     }
-
-    fn on_output(&mut self, output: &str, _session_id: &str) -> String {
-        // 简单的颜色处理，可以扩展
-        output.to_string()
-    }
 }
 
 // 终端管理器
@@ -121,7 +633,11 @@ impl TerminalManager {
         self.sessions.get_mut(session_id)
     }
 
-    pub fn create_session(&mut self, config: TerminalConfig, app_handle: AppHandle) -> Result<String, String> {
+    pub fn sessions(&self) -> impl Iterator<Item = &TerminalSession> {
+        self.sessions.values()
+    }
+
+    pub fn create_session(&mut self, config: TerminalConfig, app_handle: AppHandle) -> AppResult<String> {
         let session_id = uuid::Uuid::new_v4().to_string();
         
         // 创建 PTY
@@ -133,7 +649,7 @@ impl TerminalManager {
                 pixel_width: 0,
                 pixel_height: 0,
             })
-            .map_err(|e| format!("Failed to create PTY: {}", e))?;
+            .map_err(|e| AppError::pty_io(format!("Failed to create PTY: {}", e)))?;
 
         // 启动 shell
         let mut cmd = portable_pty::CommandBuilder::new(&config.shell);
@@ -148,35 +664,53 @@ impl TerminalManager {
             cmd.cwd(dir);
         }
 
-        let _child = pty_pair.slave
+        // 注入 shell 集成脚本，失败时退化为普通 shell；只接受带本会话 nonce 的标记
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        if config.shell_integration {
+            if let Err(e) = shell_integration::inject(&config.shell, &mut cmd, &config.env, &nonce) {
+                eprintln!("{}", e);
+            }
+        }
+
+        let child = pty_pair.slave
             .spawn_command(cmd)
-            .map_err(|e| format!("Failed to spawn shell: {}", e))?;
+            .map_err(|e| AppError::pty_io(format!("Failed to spawn shell: {}", e)))?;
+        let pid = child.process_id();
 
-        // 创建插件实例
-        let mut plugins: Vec<Box<dyn TerminalPlugin + Send + Sync>> = Vec::new();
-        plugins.push(Box::new(HistoryPlugin::new()));
-        plugins.push(Box::new(ColorPlugin));
-        
-        // 可以在这里添加更多插件
-        // plugins.push(Box::new(crate::plugins::TimingPlugin::new()));
-        // plugins.push(Box::new(crate::plugins::GitPlugin::new()));
-        // plugins.push(Box::new(crate::plugins::AliasPlugin::new()));
+        // 按会话配置或插件设置创建插件实例，交给会话的分发任务，任务启动时通知插件会话开始
+        let plugins = PLUGIN_REGISTRY.lock().unwrap().instantiate(config.plugins.as_deref());
+        let plugins = PluginDispatcher::spawn(&session_id, plugins);
 
-        let mut session = TerminalSession {
+        let scrollback = ScrollbackBuffer::new(config.scrollback_lines, config.scrollback_bytes);
+        let screen = VirtualScreen::new(config.rows, config.columns, config.screen_scrollback);
+        let session = TerminalSession {
             id: session_id.clone(),
             pty: Arc::new(Mutex::new(pty_pair.master)),
+            child: Arc::new(Mutex::new(child)),
+            pid,
             config,
             plugins,
             app_handle: app_handle.clone(),
+            osc_parser: OscParser::new(nonce),
+            integration_active: false,
+            pending_command: None,
+            running_command: None,
+            cwd: None,
+            scrollback,
+            command_capture: None,
+            last_command: None,
+            screen,
+            command_waiters: Vec::new(),
+            input_line: Some(String::new()),
+            pending_response: None,
+            input_lock: Arc::new(tokio::sync::Mutex::new(())),
         };
 
         // 启动输出监听
         self.start_output_listener(&session_id, &session)?;
-        
-        // 通知插件会话开始
-        for plugin in &mut session.plugins {
-            plugin.on_session_start(&session_id);
-        }
+
+        // 启动子进程回收任务
+        self.start_exit_watcher(&session_id, &session);
 
         self.sessions.insert(session_id.clone(), session);
         self.active_session = Some(session_id.clone());
@@ -184,12 +718,13 @@ impl TerminalManager {
         Ok(session_id)
     }
 
-    fn start_output_listener(&self, session_id: &str, session: &TerminalSession) -> Result<(), String> {
+    fn start_output_listener(&self, session_id: &str, session: &TerminalSession) -> AppResult<()> {
         let session_id = session_id.to_string();
         let app_handle = session.app_handle.clone();
         let pty = session.pty.clone();
         
-        tokio::spawn(async move {
+        // 读取 PTY 是阻塞操作，放到阻塞线程池中避免占用异步运行时的工作线程
+        tokio::task::spawn_blocking(move || {
             let mut reader = {
                 let pty_guard = pty.lock().unwrap();
                 pty_guard.try_clone_reader().unwrap()
@@ -206,18 +741,18 @@ impl TerminalManager {
                         let processed_output = {
                             let mut manager = TERMINAL_MANAGER.lock().unwrap();
                             if let Some(session) = manager.sessions.get_mut(&session_id) {
-                                let mut result = output.to_string();
-                                for plugin in &mut session.plugins {
-                                    result = plugin.on_output(&result, &session_id);
-                                }
-                                result
+                                session.process_output(&output)
                             } else {
                                 output.to_string()
                             }
                         };
 
                         // 发送到前端
-                        if let Err(e) = app_handle.emit("terminal-output", &processed_output) {
+                        let event = TerminalOutputEvent {
+                            session_id: session_id.clone(),
+                            data: processed_output,
+                        };
+                        if let Err(e) = app_handle.emit("terminal-output", &event) {
                             eprintln!("Failed to emit terminal output: {}", e);
                             break;
                         }
@@ -233,7 +768,44 @@ impl TerminalManager {
         Ok(())
     }
 
-    pub fn write_to_session(&mut self, session_id: &str, data: &str) -> Result<(), String> {
+    // 轮询子进程状态，shell 退出后通知前端并清理会话
+    fn start_exit_watcher(&self, session_id: &str, session: &TerminalSession) {
+        let session_id = session_id.to_string();
+        let app_handle = session.app_handle.clone();
+        let child = session.child.clone();
+
+        tokio::spawn(async move {
+            let status = loop {
+                let result = child.lock().unwrap().try_wait();
+                match result {
+                    Ok(Some(status)) => break Some(status),
+                    Ok(None) => tokio::time::sleep(EXIT_POLL_INTERVAL).await,
+                    Err(e) => {
+                        eprintln!("Failed to wait for shell process: {}", e);
+                        break None;
+                    }
+                }
+            };
+
+            if let Some(status) = status {
+                let event = TerminalExitEvent {
+                    session_id: session_id.clone(),
+                    exit_code: status.exit_code(),
+                    success: status.success(),
+                    signal: status.signal().map(|s| s.to_string()),
+                };
+                if let Err(e) = app_handle.emit("terminal-exit", &event) {
+                    eprintln!("Failed to emit terminal exit: {}", e);
+                }
+            }
+
+            // 会话可能已被 close_terminal 主动关闭
+            let mut manager = TERMINAL_MANAGER.lock().unwrap();
+            let _ = manager.remove_session(&session_id);
+        });
+    }
+
+    pub fn write_to_session(&mut self, session_id: &str, data: &str) -> AppResult<()> {
         if let Some(session) = self.sessions.get_mut(session_id) {
             let pty = session.pty.clone();
             let pty_guard = pty.lock().unwrap();
@@ -241,15 +813,59 @@ impl TerminalManager {
             let bytes = data.as_bytes();
             let ret = unsafe { libc::write(fd, bytes.as_ptr() as *const _, bytes.len()) };
             if ret < 0 {
-                return Err(format!("Failed to write to PTY: {}", Error::last_os_error()));
+                return Err(AppError::pty_io(format!("Failed to write to PTY: {}", Error::last_os_error())));
             }
             Ok(())
         } else {
-            Err("Session not found".to_string())
+            Err(AppError::session_not_found(session_id))
+        }
+    }
+
+    // 按插件的处理结果在会话中执行一条命令；启用 shell 集成时命令边界由 shell 上报
+    fn run_command(&mut self, session_id: &str, command: &str, action: CommandAction) -> AppResult<()> {
+        let session = self.sessions.get_mut(session_id)
+            .ok_or_else(|| AppError::session_not_found(session_id))?;
+        let command = match session.apply_command_action(command, action) {
+            Some(command) => command,
+            // 插件已处理，启用 shell 集成时发送空行以显示插件回复和新的提示符
+            None if session.integration_active => return self.write_to_session(session_id, "\r"),
+            None => return Ok(()),
+        };
+        if !session.integration_active {
+            session.notify_command_start(&command);
+        }
+
+        let command_with_newline = format!("{}\n", command);
+        self.write_to_session(session_id, &command_with_newline)
+    }
+
+    // 会话可以开始一条需要等待结果的命令
+    fn ensure_idle(&self, session_id: &str) -> AppResult<&TerminalSession> {
+        let session = self.sessions.get(session_id)
+            .ok_or_else(|| AppError::session_not_found(session_id))?;
+        if !session.integration_active {
+            return Err(AppError::ShellIntegrationInactive);
+        }
+        if session.running_command.is_some() || !session.command_waiters.is_empty() {
+            return Err(AppError::SessionBusy {
+                message: "Another command is still running in this session".to_string(),
+            });
+        }
+        Ok(session)
+    }
+
+    // 注册下一条命令的等待者，命令结束时通过 oneshot 通知；
+    // 必须与 run_command 在同一次加锁中调用，否则用户的命令可能先结束
+    fn begin_command(&mut self, session_id: &str) -> AppResult<oneshot::Receiver<CommandOutput>> {
+        self.ensure_idle(session_id)?;
+        let (sender, receiver) = oneshot::channel();
+        if let Some(session) = self.sessions.get_mut(session_id) {
+            session.command_waiters.push(sender);
         }
+        Ok(receiver)
     }
 
-    pub fn resize_session(&mut self, session_id: &str, cols: u16, rows: u16) -> Result<(), String> {
+    pub fn resize_session(&mut self, session_id: &str, cols: u16, rows: u16) -> AppResult<()> {
         if let Some(session) = self.sessions.get_mut(session_id) {
             let pty = session.pty.clone();
             let pty_guard = pty.lock().unwrap();
@@ -258,29 +874,41 @@ impl TerminalManager {
                 cols,
                 pixel_width: 0,
                 pixel_height: 0,
-            }).map_err(|e| format!("Failed to resize PTY: {}", e))?;
+            }).map_err(|e| AppError::pty_io(format!("Failed to resize PTY: {}", e)))?;
             
+            session.screen.resize(rows, cols);
             session.config.rows = rows;
             session.config.columns = cols;
             Ok(())
         } else {
-            Err("Session not found".to_string())
+            Err(AppError::session_not_found(session_id))
         }
     }
 
-    pub fn close_session(&mut self, session_id: &str) -> Result<(), String> {
-        if let Some(mut session) = self.sessions.remove(session_id) {
-            // 通知插件会话结束
-            for plugin in &mut session.plugins {
-                plugin.on_session_end(session_id);
-            }
-            
+    pub fn close_session(&mut self, session_id: &str) -> AppResult<()> {
+        if let Some(session) = self.sessions.get(session_id) {
+            terminate_session(session);
+        }
+        self.remove_session(session_id)
+    }
+
+    pub fn signal_session(&self, session_id: &str, signal: TerminalSignal) -> AppResult<()> {
+        if let Some(session) = self.sessions.get(session_id) {
+            session.send_signal(signal)
+        } else {
+            Err(AppError::session_not_found(session_id))
+        }
+    }
+
+    fn remove_session(&mut self, session_id: &str) -> AppResult<()> {
+        // 会话删除后插件分发任务通知插件会话结束
+        if self.sessions.remove(session_id).is_some() {
             if self.active_session.as_ref() == Some(&session_id.to_string()) {
                 self.active_session = None;
             }
             Ok(())
         } else {
-            Err("Session not found".to_string())
+            Err(AppError::session_not_found(session_id))
         }
     }
 
@@ -288,74 +916,229 @@ impl TerminalManager {
         self.active_session.as_ref()
     }
 
-    pub fn set_active_session(&mut self, session_id: String) -> Result<(), String> {
+    pub fn set_active_session(&mut self, session_id: String) -> AppResult<()> {
         if self.sessions.contains_key(&session_id) {
             self.active_session = Some(session_id);
             Ok(())
         } else {
-            Err("Session not found".to_string())
+            Err(AppError::SessionNotFound { session_id })
+        }
+    }
+}
+
+fn session_plugins(session_id: &str) -> AppResult<PluginDispatcher> {
+    let manager = TERMINAL_MANAGER.lock().unwrap();
+    manager.get_session(session_id)
+        .map(|session| session.plugins.clone())
+        .ok_or_else(|| AppError::session_not_found(session_id))
+}
+
+// 在会话中执行一条命令，先在锁外等待插件的 before_command
+pub async fn run_command_in_session(session_id: &str, command: &str) -> AppResult<()> {
+    let action = session_plugins(session_id)?.before_command(command).await;
+    TERMINAL_MANAGER.lock().unwrap().run_command(session_id, command, action)
+}
+
+// 前端转发的键盘输入，提示符下回车时先交给插件处理；
+// 等待插件期间同一会话的后续输入排队，保证发送给 shell 的顺序不变
+pub async fn send_user_input(session_id: &str, data: &str) -> AppResult<()> {
+    let input_lock = TERMINAL_MANAGER.lock().unwrap()
+        .get_session(session_id)
+        .map(|session| session.input_lock.clone())
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    let _guard = input_lock.lock().await;
+
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (line, plugins) = {
+            let mut manager = TERMINAL_MANAGER.lock().unwrap();
+            let session = manager.get_session_mut(session_id)
+                .ok_or_else(|| AppError::session_not_found(session_id))?;
+            let (forward, command) = session.process_input(rest);
+            let plugins = session.plugins.clone();
+            if !forward.is_empty() {
+                manager.write_to_session(session_id, &forward)?;
+            }
+            match command {
+                Some((line, consumed)) => {
+                    rest = &rest[consumed..];
+                    (line, plugins)
+                }
+                None => return Ok(()),
+            }
+        };
+
+        let action = plugins.before_command(&line).await;
+        let mut manager = TERMINAL_MANAGER.lock().unwrap();
+        let forward = manager.get_session_mut(session_id)
+            .ok_or_else(|| AppError::session_not_found(session_id))?
+            .finish_input_command(&line, action);
+        manager.write_to_session(session_id, &forward)?;
+    }
+    Ok(())
+}
+
+// 执行命令并等待 shell 集成上报命令结束，超时后返回已捕获的部分输出
+pub async fn execute_and_wait_in_session(session_id: &str, command: &str, timeout_ms: u64, source: CommandSource) -> AppResult<ExecuteResult> {
+    let started_at = Instant::now();
+    let (cols, plugins) = {
+        let manager = TERMINAL_MANAGER.lock().unwrap();
+        let session = manager.ensure_idle(session_id)?;
+        (session.config.columns, session.plugins.clone())
+    };
+
+    let action = match plugins.before_command(command).await {
+        CommandAction::Rewrite(rewritten) if source == CommandSource::Ai => {
+            println!("[RUST] Ignoring plugin rewrite of approved AI command: {} -> {}", command, rewritten);
+            CommandAction::Continue
+        }
+        action => action,
+    };
+    let receiver = {
+        let mut manager = TERMINAL_MANAGER.lock().unwrap();
+        let receiver = manager.begin_command(session_id)?;
+        if let Err(e) = manager.run_command(session_id, command, action) {
+            if let Some(session) = manager.get_session_mut(session_id) {
+                session.command_waiters.clear();
+            }
+            return Err(e);
+        }
+        receiver
+    };
+
+    match tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), receiver).await {
+        Ok(Ok(output)) => Ok(ExecuteResult {
+            command: command.to_string(),
+            output: screen::render_plain(&output.output, cols),
+            exit_code: output.exit_code,
+            duration_ms: output.duration_ms,
+            timed_out: false,
+            interrupted: false,
+        }),
+        Ok(Err(_)) => Err(AppError::pty_io("Session closed before the command finished")),
+        Err(_) => {
+            // 超时后中断前台命令，否则会话会一直处于忙碌状态
+            let (partial, interrupted) = {
+                let mut manager = TERMINAL_MANAGER.lock().unwrap();
+                manager.get_session_mut(session_id)
+                    .map(|session| {
+                        session.command_waiters.clear();
+                        let interrupted = session.running_command.is_some() && session.interrupt_foreground();
+                        (session.command_capture.clone().unwrap_or_default(), interrupted)
+                    })
+                    .unwrap_or_default()
+            };
+            Ok(ExecuteResult {
+                command: command.to_string(),
+                output: screen::render_plain(&partial, cols),
+                exit_code: None,
+                duration_ms: started_at.elapsed().as_millis() as u64,
+                timed_out: true,
+                interrupted,
+            })
         }
     }
 }
 
 // Tauri 命令
+// plugins 指定该会话加载的插件，不传时使用插件设置中启用的插件
 #[tauri::command]
-pub async fn create_shell(app_handle: AppHandle) -> Result<String, String> {
-    let config = TerminalConfig::default();
+pub async fn create_shell(app_handle: AppHandle, plugins: Option<Vec<String>>) -> AppResult<String> {
+    let config = TerminalConfig {
+        plugins,
+        ..TerminalConfig::default()
+    };
     let mut manager = TERMINAL_MANAGER.lock().unwrap();
     manager.create_session(config, app_handle)
 }
 
 #[tauri::command]
-pub async fn run_command_pty(command: String) -> Result<(), String> {
+pub async fn run_command_pty(session_id: String, command: String) -> AppResult<()> {
+    run_command_in_session(&session_id, &command).await
+}
+
+// 在指定会话中重新执行一条历史命令
+#[tauri::command]
+pub async fn replay_command(session_id: String, record_id: String) -> AppResult<()> {
+    let record = storage::find_command(&record_id)?;
+    run_command_in_session(&session_id, &record.command).await
+}
+
+#[tauri::command]
+pub async fn execute_and_wait(session_id: String, command: String, timeout_ms: Option<u64>) -> AppResult<ExecuteResult> {
+    execute_and_wait_in_session(&session_id, &command, timeout_ms.unwrap_or(DEFAULT_EXECUTE_TIMEOUT_MS), CommandSource::User).await
+}
+
+#[tauri::command]
+pub async fn resize_terminal(session_id: String, cols: u16, rows: u16) -> AppResult<()> {
     let mut manager = TERMINAL_MANAGER.lock().unwrap();
-    
-    if let Some(session_id) = manager.get_active_session().cloned() {
-        // 通知插件命令开始
-        if let Some(session) = manager.sessions.get_mut(&session_id) {
-            for plugin in &mut session.plugins {
-                plugin.on_command_start(&command, &session_id);
-            }
-        }
-        
-        let command_with_newline = format!("{}\n", command);
-        manager.write_to_session(&session_id, &command_with_newline)
-    } else {
-        Err("No active terminal session".to_string())
-    }
+    manager.resize_session(&session_id, cols, rows)
 }
 
 #[tauri::command]
-pub async fn resize_terminal(cols: u16, rows: u16) -> Result<(), String> {
+pub async fn close_terminal(session_id: String) -> AppResult<()> {
     let mut manager = TERMINAL_MANAGER.lock().unwrap();
-    
-    if let Some(session_id) = manager.get_active_session().cloned() {
-        manager.resize_session(&session_id, cols, rows)
-    } else {
-        Err("No active terminal session".to_string())
-    }
+    manager.close_session(&session_id)
+}
+
+#[tauri::command]
+pub async fn send_input(session_id: String, input: String) -> AppResult<()> {
+    send_user_input(&session_id, &input).await
+}
+
+#[tauri::command]
+pub async fn get_session_cwd(session_id: String) -> AppResult<String> {
+    let manager = TERMINAL_MANAGER.lock().unwrap();
+    let session = manager.get_session(&session_id)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    session.current_dir()
+        .ok_or_else(|| AppError::pty_io("Working directory unknown"))
+}
+
+#[tauri::command]
+pub async fn get_scrollback_lines(session_id: String, count: usize) -> AppResult<Vec<String>> {
+    let manager = TERMINAL_MANAGER.lock().unwrap();
+    let session = manager.get_session(&session_id)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    Ok(session.scrollback.last_lines(count))
+}
+
+#[tauri::command]
+pub async fn get_scrollback_range(session_id: String, offset: u64, count: usize) -> AppResult<ScrollbackRange> {
+    let manager = TERMINAL_MANAGER.lock().unwrap();
+    let session = manager.get_session(&session_id)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    Ok(session.scrollback.range(offset, count))
+}
+
+#[tauri::command]
+pub async fn get_last_command_output(session_id: String) -> AppResult<Option<CommandOutput>> {
+    let manager = TERMINAL_MANAGER.lock().unwrap();
+    let session = manager.get_session(&session_id)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    Ok(session.last_command.clone())
 }
 
 #[tauri::command]
-pub async fn close_terminal() -> Result<(), String> {
+pub async fn get_screen_snapshot(session_id: String, scrollback_lines: Option<usize>) -> AppResult<ScreenSnapshot> {
     let mut manager = TERMINAL_MANAGER.lock().unwrap();
-    
-    if let Some(session_id) = manager.get_active_session().cloned() {
-        manager.close_session(&session_id)
-    } else {
-        Err("No active terminal session".to_string())
-    }
+    let session = manager.get_session_mut(&session_id)
+        .ok_or_else(|| AppError::session_not_found(&session_id))?;
+    let limit = scrollback_lines.unwrap_or(session.config.screen_scrollback);
+    Ok(session.screen.snapshot(limit))
+}
+
+#[tauri::command]
+pub async fn send_signal(session_id: String, signal: TerminalSignal) -> AppResult<()> {
+    let manager = TERMINAL_MANAGER.lock().unwrap();
+    manager.signal_session(&session_id, signal)
 }
 
+// 前端切换标签页时调用，AI 相关功能作用于活动会话
 #[tauri::command]
-pub async fn send_input(input: String) -> Result<(), String> {
+pub async fn set_active_terminal(session_id: String) -> AppResult<()> {
     let mut manager = TERMINAL_MANAGER.lock().unwrap();
-    
-    if let Some(session_id) = manager.get_active_session().cloned() {
-        manager.write_to_session(&session_id, &input)
-    } else {
-        Err("No active terminal session".to_string())
-    }
+    manager.set_active_session(session_id)
 }
 
 fn get_default_shell() -> String {
@@ -368,29 +1151,25 @@ fn get_default_shell() -> String {
 
 // 新增的 Tauri 命令
 #[tauri::command]
-pub async fn get_terminal_info() -> Result<serde_json::Value, String> {
+pub async fn get_terminal_info() -> AppResult<serde_json::Value> {
     let manager = TERMINAL_MANAGER.lock().unwrap();
+    let sessions: Vec<serde_json::Value> = manager.sessions.values()
+        .map(|session| serde_json::json!({
+            "id": session.id,
+            "pid": session.pid,
+            "shell": session.config.shell,
+            "running_command": session.running_command.as_ref().map(|c| c.command.clone()),
+            "scrollback": {
+                "first_offset": session.scrollback.first_offset(),
+                "end_offset": session.scrollback.end_offset(),
+            },
+        }))
+        .collect();
     let info = serde_json::json!({
         "active_session": manager.get_active_session(),
         "total_sessions": manager.sessions.len(),
+        "sessions": sessions,
         "default_shell": get_default_shell(),
     });
     Ok(info)
 }
-
-#[tauri::command]
-pub async fn list_plugins() -> Result<Vec<String>, String> {
-    let manager = TERMINAL_MANAGER.lock().unwrap();
-    if let Some(session_id) = manager.get_active_session() {
-        if let Some(session) = manager.sessions.get(session_id) {
-            let plugin_names: Vec<String> = session.plugins.iter()
-                .map(|p| p.name().to_string())
-                .collect();
-            Ok(plugin_names)
-        } else {
-            Err("No active session".to_string())
-        }
-    } else {
-        Err("No active session".to_string())
-    }
-}
\ No newline at end of file
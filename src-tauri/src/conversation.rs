@@ -0,0 +1,293 @@
+// src/conversation.rs - AI 对话历史
+
+use crate::ai::ChatMessage;
+use crate::error::{AppError, AppResult};
+use crate::storage;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 标题截取的最大字符数
+const TITLE_MAX_CHARS: usize = 40;
+
+// 全局对话存储
+pub static CONVERSATIONS: Lazy<Mutex<ConversationStore>> =
+    Lazy::new(|| Mutex::new(ConversationStore::new()));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    // 完整历史（不含系统提示词），包括工具调用及其结果
+    pub messages: Vec<ChatMessage>,
+    // messages[..summarized_upto] 已被压缩进 summary，不再发送给模型
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub summarized_upto: usize,
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    // 绑定的 AI 配置名，为空时使用当前激活的配置
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// 列表中展示的对话概要
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationInfo {
+    pub id: String,
+    pub title: String,
+    pub message_count: usize,
+    pub parent_id: Option<String>,
+    pub profile: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Conversation {
+    pub fn new(title: &str) -> Self {
+        let now = now_millis();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            title: title.to_string(),
+            messages: Vec::new(),
+            summary: None,
+            summarized_upto: 0,
+            parent_id: None,
+            profile: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn info(&self) -> ConversationInfo {
+        ConversationInfo {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            message_count: self.messages.len(),
+            parent_id: self.parent_id.clone(),
+            profile: self.profile.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+
+    // 需要发送给模型的历史消息
+    pub fn context_messages(&self) -> &[ChatMessage] {
+        &self.messages[self.summarized_upto.min(self.messages.len())..]
+    }
+}
+
+pub struct ConversationStore {
+    conversations: HashMap<String, Conversation>,
+}
+
+impl ConversationStore {
+    pub fn new() -> Self {
+        Self {
+            conversations: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Conversation> {
+        self.conversations.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Conversation> {
+        self.conversations.get_mut(id)
+    }
+
+    pub fn insert(&mut self, conversation: Conversation) {
+        self.conversations.insert(conversation.id.clone(), conversation);
+    }
+
+    // 按最近更新时间倒序
+    pub fn list(&self) -> Vec<ConversationInfo> {
+        let mut list: Vec<ConversationInfo> = self.conversations.values().map(|c| c.info()).collect();
+        list.sort_by_key(|c| std::cmp::Reverse(c.updated_at));
+        list
+    }
+
+    // 复制对话的前 message_count 条消息作为新对话，
+    // 分叉点落在工具调用中间时退到这一轮开始之前
+    pub fn fork(&mut self, id: &str, message_count: Option<usize>) -> AppResult<Conversation> {
+        let source = self.conversations.get(id)
+            .ok_or_else(|| AppError::not_found("Conversation", id))?;
+        let count = message_count.unwrap_or(source.messages.len()).min(source.messages.len());
+        let count = turn_boundary(&source.messages, count);
+
+        let mut fork = Conversation::new(&format!("{} (fork)", source.title));
+        fork.messages = source.messages[..count].to_vec();
+        fork.parent_id = Some(source.id.clone());
+        fork.profile = source.profile.clone();
+        // 分叉点之前已有摘要时沿用，否则从头发送
+        if source.summarized_upto <= count {
+            fork.summary = source.summary.clone();
+            fork.summarized_upto = source.summarized_upto;
+        }
+
+        self.insert(fork.clone());
+        Ok(fork)
+    }
+
+    pub fn delete(&mut self, id: &str) -> AppResult<()> {
+        self.conversations.remove(id)
+            .map(|_| ())
+            .ok_or_else(|| AppError::not_found("Conversation", id))
+    }
+}
+
+// 带 tool_calls 的 assistant 消息必须和全部工具结果一起保留，否则模型接口会拒绝请求
+fn turn_boundary(messages: &[ChatMessage], mut count: usize) -> usize {
+    while count > 0 {
+        let splits_results = messages.get(count).is_some_and(|m| m.role == "tool");
+        let pending_calls = messages[count - 1].tool_calls.as_ref().is_some_and(|calls| !calls.is_empty());
+        if !splits_results && !pending_calls {
+            break;
+        }
+        count -= 1;
+    }
+    count
+}
+
+// 修改对话绑定的 AI 配置
+pub fn set_profile(conversation_id: &str, profile: Option<&str>) -> AppResult<()> {
+    let mut store = CONVERSATIONS.lock().unwrap();
+    let conversation = store.get_mut(conversation_id)
+        .ok_or_else(|| AppError::not_found("Conversation", conversation_id))?;
+    if conversation.profile.as_deref() != profile {
+        conversation.profile = profile.map(|p| p.to_string());
+        storage::save_conversation(conversation);
+    }
+    Ok(())
+}
+
+// 由第一条用户消息生成标题
+pub fn title_from_message(message: &str) -> String {
+    let line = message.lines().next().unwrap_or("").trim();
+    let mut title: String = line.chars().take(TITLE_MAX_CHARS).collect();
+    if line.chars().count() > TITLE_MAX_CHARS {
+        title.push('…');
+    }
+    if title.is_empty() {
+        "New conversation".to_string()
+    } else {
+        title
+    }
+}
+
+// 粗略估算 token 数：约 4 个字符一个 token，外加每条消息的固定开销
+pub fn estimate_tokens(messages: &[ChatMessage]) -> usize {
+    messages.iter().map(estimate_message_tokens).sum()
+}
+
+pub fn estimate_message_tokens(message: &ChatMessage) -> usize {
+    let mut chars = message.content.as_ref().map(|c| c.chars().count()).unwrap_or(0);
+    if let Some(tool_calls) = &message.tool_calls {
+        for call in tool_calls {
+            chars += call.function.name.len() + call.function.arguments.chars().count();
+        }
+    }
+    chars / 4 + 4
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Tauri 命令
+#[tauri::command]
+pub fn list_conversations() -> AppResult<Vec<ConversationInfo>> {
+    Ok(CONVERSATIONS.lock().unwrap().list())
+}
+
+// 恢复对话时获取完整历史，之后带上 conversation_id 继续聊天即可
+#[tauri::command]
+pub fn get_conversation(conversation_id: String) -> AppResult<Conversation> {
+    CONVERSATIONS.lock().unwrap()
+        .get(&conversation_id)
+        .cloned()
+        .ok_or_else(|| AppError::not_found("Conversation", &conversation_id))
+}
+
+#[tauri::command]
+pub fn fork_conversation(conversation_id: String, message_count: Option<usize>) -> AppResult<Conversation> {
+    let fork = CONVERSATIONS.lock().unwrap().fork(&conversation_id, message_count)?;
+    storage::save_conversation(&fork);
+    Ok(fork)
+}
+
+#[tauri::command]
+pub fn set_conversation_profile(conversation_id: String, profile: Option<String>) -> AppResult<()> {
+    if let Some(name) = &profile {
+        crate::profiles::get_profile_config(name)?;
+    }
+    set_profile(&conversation_id, profile.as_deref())
+}
+
+#[tauri::command]
+pub fn delete_conversation(conversation_id: String) -> AppResult<()> {
+    CONVERSATIONS.lock().unwrap().delete(&conversation_id)?;
+    storage::delete_conversation(&conversation_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{FunctionCall, ToolCall};
+
+    fn tool_call_message(id: &str) -> ChatMessage {
+        ChatMessage {
+            role: "assistant".to_string(),
+            content: None,
+            tool_calls: Some(vec![ToolCall {
+                id: id.to_string(),
+                r#type: "function".to_string(),
+                function: FunctionCall {
+                    name: "execute_command".to_string(),
+                    arguments: r#"{"command":"ls"}"#.to_string(),
+                },
+            }]),
+            tool_call_id: None,
+        }
+    }
+
+    fn tool_result(id: &str) -> ChatMessage {
+        ChatMessage {
+            role: "tool".to_string(),
+            content: Some("file".to_string()),
+            tool_calls: None,
+            tool_call_id: Some(id.to_string()),
+        }
+    }
+
+    #[test]
+    fn fork_keeps_tool_calls_with_their_results() {
+        let mut store = ConversationStore::new();
+        let mut source = Conversation::new("source");
+        source.messages = vec![
+            ChatMessage::new("user", "list files"),
+            tool_call_message("call_1"),
+            tool_result("call_1"),
+            ChatMessage::new("assistant", "one file"),
+            ChatMessage::new("user", "thanks"),
+            ChatMessage::new("assistant", "you're welcome"),
+        ];
+        let id = source.id.clone();
+        store.insert(source);
+
+        let lengths: Vec<usize> = [Some(1), Some(2), Some(3), Some(4), Some(5), None]
+            .into_iter()
+            .map(|count| store.fork(&id, count).unwrap().messages.len())
+            .collect();
+        assert_eq!(lengths, vec![1, 1, 3, 4, 5, 6]);
+    }
+}
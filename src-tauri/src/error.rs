@@ -0,0 +1,173 @@
+// src/error.rs - 所有 Tauri 命令统一返回的错误类型
+//
+// 序列化为 { "kind": "session_not_found", "message": "...", ...附加字段 }，
+// 前端按 kind 判断错误类型，message 只用于显示。
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error)]
+pub enum AppError {
+    #[error("Session not found: {session_id}")]
+    SessionNotFound { session_id: String },
+
+    #[error("No active terminal session")]
+    NoActiveSession,
+
+    // 会话中已有命令在执行，或没有启用 shell 集成无法等待命令结束
+    #[error("{message}")]
+    SessionBusy { message: String },
+
+    #[error("Shell integration is not active in this session")]
+    ShellIntegrationInactive,
+
+    #[error("{message}")]
+    PtyIo { message: String },
+
+    #[error("{message}")]
+    AiNotConfigured { message: String },
+
+    // 无法连接模型服务或读取响应
+    #[error("{message}")]
+    AiNetwork { message: String },
+
+    // 模型服务返回非 2xx 且响应体无法解析为错误对象
+    #[error("API Error ({status}): {message}")]
+    AiHttp { status: u16, message: String },
+
+    // 模型服务返回的结构化错误
+    #[error("{message}")]
+    AiApi {
+        code: Option<String>,
+        r#type: Option<String>,
+        message: String,
+    },
+
+    #[error("Request cancelled")]
+    Cancelled,
+
+    // 工具调用或命令参数不合法
+    #[error("{message}")]
+    InvalidArgument { message: String },
+
+    #[error("Invalid configuration: {message}")]
+    ConfigInvalid { message: String },
+
+    #[error("Command denied: {reason}")]
+    PolicyDenied { reason: String },
+
+    #[error("Command denied by user")]
+    ApprovalRejected,
+
+    #[error("Command requires user approval, but no approval UI is available")]
+    ApprovalUnavailable,
+
+    #[error("{what} not found: {id}")]
+    NotFound { what: String, id: String },
+
+    #[error("{message}")]
+    Io { message: String },
+
+    #[error("{message}")]
+    Internal { message: String },
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::SessionNotFound { .. } => "session_not_found",
+            AppError::NoActiveSession => "no_active_session",
+            AppError::SessionBusy { .. } => "session_busy",
+            AppError::ShellIntegrationInactive => "shell_integration_inactive",
+            AppError::PtyIo { .. } => "pty_io",
+            AppError::AiNotConfigured { .. } => "ai_not_configured",
+            AppError::AiNetwork { .. } => "ai_network",
+            AppError::AiHttp { .. } => "ai_http",
+            AppError::AiApi { .. } => "ai_api",
+            AppError::Cancelled => "cancelled",
+            AppError::InvalidArgument { .. } => "invalid_argument",
+            AppError::ConfigInvalid { .. } => "config_invalid",
+            AppError::PolicyDenied { .. } => "policy_denied",
+            AppError::ApprovalRejected => "approval_rejected",
+            AppError::ApprovalUnavailable => "approval_unavailable",
+            AppError::NotFound { .. } => "not_found",
+            AppError::Io { .. } => "io",
+            AppError::Internal { .. } => "internal",
+        }
+    }
+
+    pub fn session_not_found(session_id: &str) -> Self {
+        AppError::SessionNotFound { session_id: session_id.to_string() }
+    }
+
+    pub fn not_found(what: &str, id: &str) -> Self {
+        AppError::NotFound {
+            what: what.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        AppError::InvalidArgument { message: message.into() }
+    }
+
+    pub fn config_invalid(message: impl Into<String>) -> Self {
+        AppError::ConfigInvalid { message: message.into() }
+    }
+
+    pub fn pty_io(message: impl Into<String>) -> Self {
+        AppError::PtyIo { message: message.into() }
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        AppError::Io { message: message.into() }
+    }
+
+    pub fn ai_network(message: impl Into<String>) -> Self {
+        AppError::AiNetwork { message: message.into() }
+    }
+}
+
+// 内部模块仍返回字符串错误的地方，统一归为 Internal
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Internal { message }
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        AppError::Internal { message: message.to_string() }
+    }
+}
+
+impl From<tokio::task::JoinError> for AppError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        AppError::Internal { message: e.to_string() }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            AppError::SessionNotFound { session_id } => map.serialize_entry("session_id", session_id)?,
+            AppError::AiHttp { status, .. } => map.serialize_entry("status", status)?,
+            AppError::AiApi { code, r#type, .. } => {
+                map.serialize_entry("code", code)?;
+                map.serialize_entry("type", r#type)?;
+            }
+            AppError::PolicyDenied { reason } => map.serialize_entry("reason", reason)?,
+            AppError::NotFound { what, id } => {
+                map.serialize_entry("what", what)?;
+                map.serialize_entry("id", id)?;
+            }
+            _ => {}
+        }
+        map.end()
+    }
+}
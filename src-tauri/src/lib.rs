@@ -1,40 +1,203 @@
 // src/main.rs
+mod error;
 mod commands;
 mod ai;
+mod shell_integration;
+mod scrollback;
+mod screen;
+mod conversation;
+mod storage;
+mod providers;
+mod profiles;
+mod secrets;
+mod policy;
+mod sandbox;
+mod mcp_server;
+mod mcp_client;
+mod plugins;
+
+use tauri::Manager;
 
 use commands::{
     create_shell, 
     run_command_pty, 
+    replay_command,
+    execute_and_wait,
     resize_terminal, 
     close_terminal, 
     send_input,
     get_terminal_info,
-    list_plugins,
+    set_active_terminal,
+    send_signal,
+    get_session_cwd,
+    get_scrollback_lines,
+    get_scrollback_range,
+    get_last_command_output,
+    get_screen_snapshot,
 };
 
 use ai::{
     configure_ai,
     chat_with_ai,
+    chat_with_ai_stream,
+    cancel_chat,
     get_ai_config
 };
 
+use conversation::{
+    list_conversations,
+    get_conversation,
+    fork_conversation,
+    set_conversation_profile,
+    delete_conversation,
+};
+
+use profiles::{
+    list_ai_profiles,
+    save_ai_profile,
+    delete_ai_profile,
+    switch_ai_profile,
+};
+
+use policy::{
+    respond_command_approval,
+    classify_command,
+};
+
+use mcp_server::{
+    get_mcp_server_status,
+    set_mcp_server_enabled,
+    regenerate_mcp_token,
+};
+
+use mcp_client::{
+    list_mcp_connections,
+    save_mcp_connection,
+    delete_mcp_connection,
+    set_mcp_connection_enabled,
+    reconnect_mcp_connection,
+};
+
+use plugins::{
+    list_plugins,
+    enable_plugin,
+    disable_plugin,
+    execute_plugin_command,
+};
+
+use storage::{
+    search_command_history,
+    get_command_record,
+    list_tool_invocations,
+    search_conversations,
+};
+
+// 作为 stdio MCP 服务运行，转发给已打开的 chatshell
+pub fn run_mcp_stdio() {
+    let runtime = tokio::runtime::Runtime::new().expect("failed to create tokio runtime");
+    if let Err(e) = runtime.block_on(mcp_server::serve_stdio()) {
+        eprintln!("MCP stdio server error: {}", e);
+        std::process::exit(1);
+    }
+}
+
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .setup(|app| {
+            // 历史记录保存在应用数据目录，失败时仅保留在内存中
+            match app.path().app_data_dir() {
+                Ok(dir) => {
+                    if let Err(e) = storage::init(dir.join("history")) {
+                        eprintln!("Failed to initialize history storage: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to resolve app data dir: {}", e),
+            }
+            // AI 配置保存在应用配置目录
+            match app.path().app_config_dir() {
+                Ok(dir) => {
+                    if let Err(e) = profiles::init(dir.clone()) {
+                        eprintln!("Failed to load AI profiles: {}", e);
+                    }
+                    if let Err(e) = mcp_server::init(app.handle().clone(), dir.clone()) {
+                        eprintln!("Failed to load MCP server settings: {}", e);
+                    }
+                    if let Err(e) = mcp_client::init(dir.clone()) {
+                        eprintln!("Failed to load MCP servers: {}", e);
+                    }
+                    if let Err(e) = plugins::init(app.handle().clone(), dir) {
+                        eprintln!("Failed to load plugin settings: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to resolve app config dir: {}", e),
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             create_shell,
             run_command_pty,
+            replay_command,
+            execute_and_wait,
             resize_terminal,
             close_terminal,
             send_input,
             get_terminal_info,
-            list_plugins,
+            set_active_terminal,
+            send_signal,
+            get_session_cwd,
+            get_scrollback_lines,
+            get_scrollback_range,
+            get_last_command_output,
+            get_screen_snapshot,
             // AI相关命令
             configure_ai,
             chat_with_ai,
-            get_ai_config
+            chat_with_ai_stream,
+            cancel_chat,
+            get_ai_config,
+            // 对话历史
+            list_conversations,
+            get_conversation,
+            fork_conversation,
+            set_conversation_profile,
+            delete_conversation,
+            // AI 配置
+            list_ai_profiles,
+            save_ai_profile,
+            delete_ai_profile,
+            switch_ai_profile,
+            // 持久化历史
+            search_command_history,
+            get_command_record,
+            list_tool_invocations,
+            search_conversations,
+            // 命令审批
+            respond_command_approval,
+            classify_command,
+            // MCP 服务
+            get_mcp_server_status,
+            set_mcp_server_enabled,
+            regenerate_mcp_token,
+            // 外部 MCP 服务
+            list_mcp_connections,
+            save_mcp_connection,
+            delete_mcp_connection,
+            set_mcp_connection_enabled,
+            reconnect_mcp_connection,
+            // 插件
+            list_plugins,
+            enable_plugin,
+            disable_plugin,
+            execute_plugin_command
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            // 退出前写完尚未落盘的历史记录
+            if let tauri::RunEvent::Exit = event {
+                storage::flush();
+            }
+        });
 }
\ No newline at end of file
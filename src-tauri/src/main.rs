@@ -1,3 +1,7 @@
 fn main() {
+    if std::env::args().any(|arg| arg == "--mcp-stdio") {
+        chatshell_lib::run_mcp_stdio();
+        return;
+    }
     chatshell_lib::run()
-}
\ No newline at end of file
+}
@@ -0,0 +1,831 @@
+// src/mcp_client.rs - 连接外部 MCP 服务，把它们的工具和资源提供给 AI
+//
+// 服务列表保存在应用配置目录的 mcp_servers.json 中，每个服务可以是：
+//   { "name": "fs", "command": "npx", "args": ["-y", "@modelcontextprotocol/server-filesystem", "/tmp"] }
+//   { "name": "remote", "url": "http://127.0.0.1:8000/mcp", "headers": { "Authorization": "Bearer ..." } }
+// stdio 服务由 rust-mcp-sdk 启动子进程，URL 服务使用 Streamable HTTP 传输。
+//
+// 暴露给模型的工具名为 mcp__<服务名>__<工具名>；服务提供资源时额外提供
+// mcp__<服务名>__read_resource，描述中列出可读取的资源。净化或截断后重名的工具加上序号。
+// 调用工具前按当前 AI 配置的审批规则确认，"trusted": true 的服务不再逐次确认。
+
+use crate::ai::Tool;
+use crate::error::{AppError, AppResult};
+use crate::policy::{self, Classification};
+use crate::secrets;
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use rust_mcp_schema::{
+    CallToolRequestParams, ClientCapabilities, Implementation, InitializeRequestParams, ListResourcesRequestParams,
+    ListToolsRequestParams, ReadResourceRequestParams, RpcError, LATEST_PROTOCOL_VERSION,
+};
+use rust_mcp_sdk::mcp_client::{client_runtime, ClientHandler, ClientRuntime};
+use rust_mcp_sdk::{MCPClient, StdioTransport, TransportOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const SERVERS_FILE: &str = "mcp_servers.json";
+const TOOL_PREFIX: &str = "mcp__";
+const READ_RESOURCE_TOOL: &str = "read_resource";
+
+// OpenAI 等接口限制工具名最长 64 个字符
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+// 资源过多时只在工具描述中列出前面一部分
+const MAX_LISTED_RESOURCES: usize = 50;
+
+// 分页接口最多读取的页数，防止服务端返回循环的 cursor
+const MAX_LIST_PAGES: usize = 20;
+
+const REQUEST_TIMEOUT_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpServerConfig {
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // 调用工具时不需要确认
+    #[serde(default)]
+    pub trusted: bool,
+    #[serde(flatten)]
+    pub transport: McpTransport,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum McpTransport {
+    Stdio {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+    },
+    Http {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Disabled,
+    Connecting,
+    Connected,
+    Failed,
+}
+
+// 返回给前端的服务状态
+#[derive(Debug, Clone, Serialize)]
+pub struct McpConnectionStatus {
+    #[serde(flatten)]
+    pub config: McpServerConfig,
+    pub state: ConnectionState,
+    pub error: Option<String>,
+    pub tools: Vec<String>,
+    pub resources: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct RemoteTool {
+    // 暴露给模型的名称
+    exposed_name: String,
+    name: String,
+    description: String,
+    input_schema: Value,
+}
+
+#[derive(Debug, Clone)]
+struct RemoteResource {
+    uri: String,
+    name: String,
+    description: Option<String>,
+}
+
+struct ServerEntry {
+    config: McpServerConfig,
+    state: ConnectionState,
+    connection: Option<Arc<Connection>>,
+    tools: Vec<RemoteTool>,
+    resources: Vec<RemoteResource>,
+    // 读取资源的工具名，没有资源时为 None
+    resource_tool: Option<String>,
+    error: Option<String>,
+}
+
+impl ServerEntry {
+    fn new(config: McpServerConfig) -> Self {
+        let state = if config.enabled { ConnectionState::Connecting } else { ConnectionState::Disabled };
+        Self {
+            config,
+            state,
+            connection: None,
+            tools: Vec::new(),
+            resources: Vec::new(),
+            resource_tool: None,
+            error: None,
+        }
+    }
+
+    fn status(&self) -> McpConnectionStatus {
+        McpConnectionStatus {
+            config: self.config.clone(),
+            state: self.state.clone(),
+            error: self.error.clone(),
+            tools: self.tools.iter().map(|t| t.name.clone()).collect(),
+            resources: self.resources.iter().map(|r| r.uri.clone()).collect(),
+        }
+    }
+
+    // 断开后返回旧连接，由调用方在锁外关闭
+    fn reset(&mut self) -> Option<Arc<Connection>> {
+        self.tools.clear();
+        self.resources.clear();
+        self.resource_tool = None;
+        self.error = None;
+        self.state = if self.config.enabled { ConnectionState::Connecting } else { ConnectionState::Disabled };
+        self.connection.take()
+    }
+
+    fn exposed_names(&self) -> impl Iterator<Item = &String> {
+        self.tools.iter().map(|t| &t.exposed_name).chain(self.resource_tool.as_ref())
+    }
+}
+
+#[derive(Default)]
+pub struct McpClientManager {
+    // 未初始化时只保存在内存中
+    path: Option<PathBuf>,
+    servers: Vec<ServerEntry>,
+}
+
+impl McpClientManager {
+    fn get_mut(&mut self, name: &str) -> Option<&mut ServerEntry> {
+        self.servers.iter_mut().find(|s| s.config.name == name)
+    }
+
+    fn save(&self) -> AppResult<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let configs: Vec<&McpServerConfig> = self.servers.iter().map(|s| &s.config).collect();
+        let content = serde_json::to_vec_pretty(&configs).map_err(|e| AppError::io(e.to_string()))?;
+        // 环境变量和请求头中可能有访问令牌
+        secrets::write_private(path, &content).map_err(AppError::io)
+    }
+
+    // 其他服务已经占用的工具名
+    fn taken_names(&self, except: &str) -> HashSet<String> {
+        self.servers.iter()
+            .filter(|s| s.config.name != except)
+            .flat_map(|s| s.exposed_names().cloned())
+            .collect()
+    }
+
+    fn status(&self, name: &str) -> AppResult<McpConnectionStatus> {
+        self.servers.iter()
+            .find(|s| s.config.name == name)
+            .map(|s| s.status())
+            .ok_or_else(|| AppError::not_found("MCP server", name))
+    }
+}
+
+pub static MCP_CLIENTS: Lazy<Mutex<McpClientManager>> = Lazy::new(|| Mutex::new(McpClientManager::default()));
+
+// 工具名只能包含字母、数字、下划线和短横线
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+fn exposed_tool_name(server: &str, tool: &str) -> String {
+    let mut name = format!("{}{}__{}", TOOL_PREFIX, sanitize_name(server), sanitize_name(tool));
+    name.truncate(MAX_TOOL_NAME_LEN);
+    name
+}
+
+// 重名时在末尾加上序号，例如 my.fs 和 my_fs 的同名工具
+fn unique_tool_name(base: &str, taken: &mut HashSet<String>) -> String {
+    let mut name = base.to_string();
+    let mut index = 2;
+    while taken.contains(&name) {
+        let suffix = format!("_{}", index);
+        name = format!("{}{}", &base[..base.len().min(MAX_TOOL_NAME_LEN - suffix.len())], suffix);
+        index += 1;
+    }
+    taken.insert(name.clone());
+    name
+}
+
+// ---------------------------------------------------------------------------
+// 连接
+
+struct ClientEvents;
+
+#[async_trait]
+impl ClientHandler for ClientEvents {
+    // stdio 服务的 stderr 只作为日志
+    async fn handle_process_error(&self, error_message: String, _runtime: &dyn MCPClient) -> Result<(), RpcError> {
+        println!("[RUST] MCP server stderr: {}", error_message);
+        Ok(())
+    }
+}
+
+enum Connection {
+    Stdio(Arc<ClientRuntime>),
+    Http(HttpConnection),
+}
+
+impl Connection {
+    async fn open(config: &McpServerConfig) -> Result<Self, String> {
+        match &config.transport {
+            McpTransport::Stdio { command, args, env } => {
+                let transport = StdioTransport::create_with_server_launch(
+                    command.clone(),
+                    args.clone(),
+                    Some(env.clone()),
+                    TransportOptions { timeout: REQUEST_TIMEOUT_MS },
+                ).map_err(|e| e.to_string())?;
+                let client = client_runtime::create_client(client_details(), transport, ClientEvents);
+                client.clone().start().await.map_err(|e| format!("Failed to start {}: {}", command, e))?;
+                Ok(Connection::Stdio(client))
+            }
+            McpTransport::Http { url, headers } => {
+                let connection = HttpConnection::new(url, headers)?;
+                connection.initialize().await?;
+                Ok(Connection::Http(connection))
+            }
+        }
+    }
+
+    async fn list_tools(&self) -> Result<Vec<Value>, String> {
+        let mut tools = Vec::new();
+        let mut cursor = None;
+        for _ in 0..MAX_LIST_PAGES {
+            let (page, next) = match self {
+                Connection::Stdio(client) => {
+                    let result = client.list_tools(Some(ListToolsRequestParams { cursor }))
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let page = result.tools.iter().filter_map(|t| serde_json::to_value(t).ok()).collect();
+                    (page, result.next_cursor)
+                }
+                Connection::Http(http) => {
+                    let result = http.request("tools/list", json!({ "cursor": cursor })).await?;
+                    page_of(result, "tools")
+                }
+            };
+            tools.extend(page);
+            match next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        Ok(tools)
+    }
+
+    async fn list_resources(&self) -> Result<Vec<Value>, String> {
+        let mut resources = Vec::new();
+        let mut cursor = None;
+        for _ in 0..MAX_LIST_PAGES {
+            let (page, next) = match self {
+                Connection::Stdio(client) => {
+                    let result = client.list_resources(Some(ListResourcesRequestParams { cursor }))
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let page = result.resources.iter().filter_map(|r| serde_json::to_value(r).ok()).collect();
+                    (page, result.next_cursor)
+                }
+                Connection::Http(http) => {
+                    let result = http.request("resources/list", json!({ "cursor": cursor })).await?;
+                    page_of(result, "resources")
+                }
+            };
+            resources.extend(page);
+            match next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        Ok(resources)
+    }
+
+    fn has_resources(&self) -> bool {
+        match self {
+            Connection::Stdio(client) => client.server_has_resources().unwrap_or(false),
+            Connection::Http(http) => http.capabilities.lock().unwrap().get("resources").is_some(),
+        }
+    }
+
+    async fn call_tool(&self, name: &str, arguments: serde_json::Map<String, Value>) -> Result<Value, String> {
+        match self {
+            Connection::Stdio(client) => {
+                let params = CallToolRequestParams {
+                    name: name.to_string(),
+                    arguments: Some(arguments),
+                };
+                let result = client.call_tool(params).await.map_err(|e| e.to_string())?;
+                serde_json::to_value(result).map_err(|e| e.to_string())
+            }
+            Connection::Http(http) => http.request("tools/call", json!({ "name": name, "arguments": arguments })).await,
+        }
+    }
+
+    async fn read_resource(&self, uri: &str) -> Result<Value, String> {
+        match self {
+            Connection::Stdio(client) => {
+                let params = ReadResourceRequestParams { uri: uri.to_string() };
+                let result = client.read_resource(params).await.map_err(|e| e.to_string())?;
+                serde_json::to_value(result).map_err(|e| e.to_string())
+            }
+            Connection::Http(http) => http.request("resources/read", json!({ "uri": uri })).await,
+        }
+    }
+
+    async fn close(&self) {
+        match self {
+            Connection::Stdio(client) => {
+                if let Err(e) = client.shut_down().await {
+                    eprintln!("Failed to shut down MCP server: {}", e);
+                }
+            }
+            Connection::Http(http) => http.close().await,
+        }
+    }
+}
+
+fn client_details() -> InitializeRequestParams {
+    InitializeRequestParams {
+        capabilities: ClientCapabilities::default(),
+        client_info: Implementation {
+            name: "chatshell".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        },
+        protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+    }
+}
+
+fn page_of(mut result: Value, key: &str) -> (Vec<Value>, Option<String>) {
+    let items = match result.get_mut(key).map(Value::take) {
+        Some(Value::Array(items)) => items,
+        _ => Vec::new(),
+    };
+    let next = result.get("nextCursor").and_then(|c| c.as_str()).map(String::from);
+    (items, next)
+}
+
+// Streamable HTTP 传输：每个请求单独 POST，响应可能是 JSON，也可能是只包含该响应的 SSE 流
+struct HttpConnection {
+    client: reqwest::Client,
+    url: String,
+    headers: reqwest::header::HeaderMap,
+    // 服务端在 initialize 响应中分配的会话 ID
+    session_id: Mutex<Option<String>>,
+    capabilities: Mutex<Value>,
+    next_id: AtomicU64,
+}
+
+impl HttpConnection {
+    fn new(url: &str, headers: &HashMap<String, String>) -> Result<Self, String> {
+        let mut header_map = reqwest::header::HeaderMap::new();
+        for (name, value) in headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| format!("Invalid header {}: {}", name, e))?;
+            let value = reqwest::header::HeaderValue::from_str(value)
+                .map_err(|e| format!("Invalid value for header {}: {}", name, e))?;
+            header_map.insert(name, value);
+        }
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(REQUEST_TIMEOUT_MS))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            headers: header_map,
+            session_id: Mutex::new(None),
+            capabilities: Mutex::new(Value::Null),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    async fn initialize(&self) -> Result<(), String> {
+        let params = serde_json::to_value(client_details()).map_err(|e| e.to_string())?;
+        let result = self.request("initialize", params).await?;
+        *self.capabilities.lock().unwrap() = result.get("capabilities").cloned().unwrap_or(Value::Null);
+        self.post(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await?;
+        Ok(())
+    }
+
+    async fn post(&self, message: Value) -> Result<reqwest::Response, String> {
+        let mut request = self.client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .header(reqwest::header::ACCEPT, "application/json, text/event-stream")
+            .json(&message);
+        if let Some(session_id) = self.session_id.lock().unwrap().as_ref() {
+            request = request.header("Mcp-Session-Id", session_id);
+        }
+        let response = request.send().await.map_err(|e| format!("Failed to reach {}: {}", self.url, e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("MCP server returned HTTP {}: {}", status, body));
+        }
+        if let Some(session_id) = response.headers().get("mcp-session-id").and_then(|v| v.to_str().ok()) {
+            *self.session_id.lock().unwrap() = Some(session_id.to_string());
+        }
+        Ok(response)
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let response = self.post(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await?;
+        let is_sse = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        let body = response.text().await.map_err(|e| e.to_string())?;
+
+        let message = if is_sse {
+            body.lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+                .find(|message| message.get("id").and_then(|i| i.as_u64()) == Some(id))
+                .ok_or_else(|| format!("No response to {} from MCP server", method))?
+        } else {
+            serde_json::from_str::<Value>(&body).map_err(|e| format!("Invalid response from MCP server: {}", e))?
+        };
+
+        if let Some(error) = message.get("error") {
+            let text = error.get("message").and_then(|m| m.as_str()).unwrap_or("Unknown error");
+            return Err(format!("MCP error: {}", text));
+        }
+        message.get("result").cloned().ok_or_else(|| "Invalid response from MCP server".to_string())
+    }
+
+    // 通知服务端结束会话，失败不影响本地清理
+    async fn close(&self) {
+        let session_id = self.session_id.lock().unwrap().clone();
+        if let Some(session_id) = session_id {
+            let _ = self.client
+                .delete(&self.url)
+                .headers(self.headers.clone())
+                .header("Mcp-Session-Id", session_id)
+                .send()
+                .await;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// 连接管理
+
+// 应用启动时调用：载入服务列表并在后台连接已启用的服务
+pub fn init(dir: PathBuf) -> AppResult<()> {
+    let path = dir.join(SERVERS_FILE);
+    let configs = load_configs(&path)?;
+    let names: Vec<String> = configs.iter().filter(|c| c.enabled).map(|c| c.name.clone()).collect();
+    {
+        let mut manager = MCP_CLIENTS.lock().unwrap();
+        manager.path = Some(path);
+        manager.servers = configs.into_iter().map(ServerEntry::new).collect();
+        println!("[RUST] Loaded {} MCP servers", manager.servers.len());
+    }
+    for name in names {
+        tauri::async_runtime::spawn(async move { connect(&name).await });
+    }
+    Ok(())
+}
+
+fn load_configs(path: &Path) -> AppResult<Vec<McpServerConfig>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| AppError::config_invalid(format!("Failed to parse {}: {}", path.display(), e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(AppError::io(format!("Failed to read {}: {}", path.display(), e))),
+    }
+}
+
+// 连接服务并读取工具和资源列表，结果记录在服务状态中
+async fn connect(name: &str) {
+    let config = {
+        let mut manager = MCP_CLIENTS.lock().unwrap();
+        match manager.get_mut(name) {
+            Some(entry) if entry.config.enabled => {
+                entry.state = ConnectionState::Connecting;
+                entry.config.clone()
+            }
+            _ => return,
+        }
+    };
+
+    println!("[RUST] Connecting to MCP server {}", name);
+    let result = match Connection::open(&config).await {
+        Ok(connection) => {
+            let connection = Arc::new(connection);
+            discover(&config.name, &connection).await.map(|(tools, resources)| (connection, tools, resources))
+        }
+        Err(e) => Err(e),
+    };
+
+    let stale = {
+        let mut manager = MCP_CLIENTS.lock().unwrap();
+        let mut taken = manager.taken_names(name);
+        match (manager.get_mut(name), result) {
+            // 连接期间配置被修改或删除，丢弃这次的连接
+            (Some(entry), Ok((connection, mut tools, resources))) if entry.config == config && entry.connection.is_none() => {
+                println!("[RUST] MCP server {} provides {} tools and {} resources", name, tools.len(), resources.len());
+                for tool in &mut tools {
+                    tool.exposed_name = unique_tool_name(&tool.exposed_name, &mut taken);
+                }
+                entry.resource_tool = (!resources.is_empty())
+                    .then(|| unique_tool_name(&exposed_tool_name(name, READ_RESOURCE_TOOL), &mut taken));
+                entry.state = ConnectionState::Connected;
+                entry.connection = Some(connection);
+                entry.tools = tools;
+                entry.resources = resources;
+                entry.error = None;
+                None
+            }
+            (_, Ok((connection, _, _))) => Some(connection),
+            (Some(entry), Err(e)) if entry.config == config => {
+                eprintln!("Failed to connect to MCP server {}: {}", name, e);
+                entry.state = ConnectionState::Failed;
+                entry.error = Some(e);
+                None
+            }
+            (_, Err(_)) => None,
+        }
+    };
+    if let Some(connection) = stale {
+        connection.close().await;
+    }
+}
+
+async fn discover(server: &str, connection: &Connection) -> Result<(Vec<RemoteTool>, Vec<RemoteResource>), String> {
+    let tools = connection.list_tools().await?
+        .into_iter()
+        .filter_map(|tool| {
+            let name = tool.get("name")?.as_str()?.to_string();
+            Some(RemoteTool {
+                exposed_name: exposed_tool_name(server, &name),
+                description: tool.get("description").and_then(|d| d.as_str()).unwrap_or_default().to_string(),
+                input_schema: tool.get("inputSchema").cloned().unwrap_or_else(|| json!({ "type": "object" })),
+                name,
+            })
+        })
+        .collect();
+
+    // 资源是可选能力，读取失败不影响工具的使用
+    let resources = if connection.has_resources() {
+        match connection.list_resources().await {
+            Ok(resources) => resources.into_iter()
+                .filter_map(|resource| Some(RemoteResource {
+                    uri: resource.get("uri")?.as_str()?.to_string(),
+                    name: resource.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_string(),
+                    description: resource.get("description").and_then(|d| d.as_str()).map(String::from),
+                }))
+                .collect(),
+            Err(e) => {
+                eprintln!("Failed to list resources of MCP server {}: {}", server, e);
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    Ok((tools, resources))
+}
+
+async fn disconnect(connection: Option<Arc<Connection>>) {
+    if let Some(connection) = connection {
+        connection.close().await;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// 提供给 AI 的工具
+
+// 已连接的服务提供的全部工具
+pub fn tools() -> Vec<Tool> {
+    let manager = MCP_CLIENTS.lock().unwrap();
+    let mut tools = Vec::new();
+    for entry in manager.servers.iter().filter(|s| s.state == ConnectionState::Connected) {
+        for tool in &entry.tools {
+            let description = format!("[MCP server {}] {}", entry.config.name, tool.description);
+            tools.push(Tool::function(&tool.exposed_name, &description, tool.input_schema.clone()));
+        }
+        if let Some(resource_tool) = &entry.resource_tool {
+            let mut description = format!(
+                "[MCP server {}] Read a resource provided by this server. Available resources:",
+                entry.config.name
+            );
+            for resource in entry.resources.iter().take(MAX_LISTED_RESOURCES) {
+                description.push_str(&format!("\n- {} ({})", resource.uri, resource.name));
+                if let Some(text) = &resource.description {
+                    description.push_str(&format!(": {}", text));
+                }
+            }
+            if entry.resources.len() > MAX_LISTED_RESOURCES {
+                description.push_str(&format!("\n... and {} more", entry.resources.len() - MAX_LISTED_RESOURCES));
+            }
+            tools.push(Tool::function(
+                resource_tool,
+                &description,
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "uri": { "type": "string", "description": "URI of the resource to read" }
+                    },
+                    "required": ["uri"]
+                }),
+            ));
+        }
+    }
+    tools
+}
+
+pub fn is_external_tool(name: &str) -> bool {
+    name.starts_with(TOOL_PREFIX)
+}
+
+// 需要审批的调用内容及其风险分级，读取资源按只读处理
+pub fn proposed_call(name: &str, arguments: &str) -> Option<(String, Classification)> {
+    let manager = MCP_CLIENTS.lock().unwrap();
+    let entry = manager.servers.iter()
+        .filter(|s| s.state == ConnectionState::Connected)
+        .find(|s| s.exposed_names().any(|n| n == name))?;
+    let tool = entry.tools.iter().find(|t| t.exposed_name == name).map(|t| t.name.as_str());
+    let read_only = entry.config.trusted || tool.is_none();
+    let classification = policy::classify_mcp_tool(&entry.config.name, tool.unwrap_or(READ_RESOURCE_TOOL), read_only);
+    Some((format!("{} {}", name, arguments), classification))
+}
+
+enum Target {
+    Tool(String),
+    Resource,
+}
+
+pub async fn call_tool(name: &str, arguments: &str) -> AppResult<String> {
+    let (connection, target) = {
+        let manager = MCP_CLIENTS.lock().unwrap();
+        let mut found = None;
+        for entry in manager.servers.iter().filter(|s| s.state == ConnectionState::Connected) {
+            if let Some(tool) = entry.tools.iter().find(|t| t.exposed_name == name) {
+                found = Some((entry.connection.clone(), Target::Tool(tool.name.clone())));
+                break;
+            }
+            if entry.resource_tool.as_deref() == Some(name) {
+                found = Some((entry.connection.clone(), Target::Resource));
+                break;
+            }
+        }
+        match found {
+            Some((Some(connection), target)) => (connection, target),
+            _ => return Err(AppError::invalid_argument(format!("Unknown tool: {}", name))),
+        }
+    };
+
+    let arguments = match serde_json::from_str::<Value>(arguments) {
+        Ok(Value::Object(map)) => map,
+        Ok(Value::Null) => serde_json::Map::new(),
+        Ok(_) => return Err(AppError::invalid_argument("Tool arguments must be a JSON object")),
+        Err(e) => return Err(AppError::invalid_argument(format!("Invalid arguments: {}", e))),
+    };
+
+    match target {
+        Target::Tool(tool) => {
+            let result = connection.call_tool(&tool, arguments).await?;
+            let text = content_to_text(result.get("content"));
+            if result.get("isError").and_then(|e| e.as_bool()).unwrap_or(false) {
+                Err(AppError::from(text))
+            } else {
+                Ok(text)
+            }
+        }
+        Target::Resource => {
+            let uri = arguments.get("uri").and_then(|u| u.as_str()).ok_or_else(|| AppError::invalid_argument("Missing uri"))?;
+            let result = connection.read_resource(uri).await?;
+            Ok(content_to_text(result.get("contents")))
+        }
+    }
+}
+
+// 把工具结果和资源内容转换成文本，二进制内容只保留说明
+fn content_to_text(content: Option<&Value>) -> String {
+    let items = match content.and_then(|c| c.as_array()) {
+        Some(items) => items,
+        None => return String::new(),
+    };
+    items.iter()
+        .map(|item| {
+            if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
+                return text.to_string();
+            }
+            if let Some(resource) = item.get("resource") {
+                return content_to_text(Some(&json!([resource])));
+            }
+            let kind = item.get("type").and_then(|t| t.as_str()).unwrap_or("binary");
+            let mime = item.get("mimeType").and_then(|m| m.as_str()).unwrap_or("unknown");
+            format!("[{} content ({}) omitted]", kind, mime)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Tauri 命令
+#[tauri::command]
+pub async fn list_mcp_connections() -> AppResult<Vec<McpConnectionStatus>> {
+    Ok(MCP_CLIENTS.lock().unwrap().servers.iter().map(|s| s.status()).collect())
+}
+
+// 新建或更新服务配置，已有连接会断开后按新配置重连
+#[tauri::command]
+pub async fn save_mcp_connection(config: McpServerConfig) -> AppResult<McpConnectionStatus> {
+    let name = config.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::config_invalid("MCP server name cannot be empty"));
+    }
+    let config = McpServerConfig { name: name.clone(), ..config };
+
+    let old = {
+        let mut manager = MCP_CLIENTS.lock().unwrap();
+        let old = match manager.get_mut(&name) {
+            Some(entry) => {
+                entry.config = config;
+                entry.reset()
+            }
+            None => {
+                manager.servers.push(ServerEntry::new(config));
+                None
+            }
+        };
+        manager.save()?;
+        old
+    };
+    disconnect(old).await;
+    connect(&name).await;
+    MCP_CLIENTS.lock().unwrap().status(&name)
+}
+
+#[tauri::command]
+pub async fn delete_mcp_connection(name: String) -> AppResult<()> {
+    let old = {
+        let mut manager = MCP_CLIENTS.lock().unwrap();
+        let index = manager.servers.iter()
+            .position(|s| s.config.name == name)
+            .ok_or_else(|| AppError::not_found("MCP server", &name))?;
+        let mut entry = manager.servers.remove(index);
+        manager.save()?;
+        entry.reset()
+    };
+    disconnect(old).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_mcp_connection_enabled(name: String, enabled: bool) -> AppResult<McpConnectionStatus> {
+    let old = {
+        let mut manager = MCP_CLIENTS.lock().unwrap();
+        let entry = manager.get_mut(&name).ok_or_else(|| AppError::not_found("MCP server", &name))?;
+        if entry.config.enabled == enabled {
+            return Ok(entry.status());
+        }
+        entry.config.enabled = enabled;
+        let old = entry.reset();
+        manager.save()?;
+        old
+    };
+    disconnect(old).await;
+    if enabled {
+        connect(&name).await;
+    }
+    MCP_CLIENTS.lock().unwrap().status(&name)
+}
+
+// 服务端工具列表变化或进程退出后手动重连
+#[tauri::command]
+pub async fn reconnect_mcp_connection(name: String) -> AppResult<McpConnectionStatus> {
+    let old = {
+        let mut manager = MCP_CLIENTS.lock().unwrap();
+        let entry = manager.get_mut(&name).ok_or_else(|| AppError::not_found("MCP server", &name))?;
+        entry.reset()
+    };
+    disconnect(old).await;
+    connect(&name).await;
+    MCP_CLIENTS.lock().unwrap().status(&name)
+}
@@ -0,0 +1,641 @@
+// src/mcp_server.rs - 以 MCP (Model Context Protocol) 服务的形式对外提供终端
+//
+// 应用内运行只监听 127.0.0.1 的 HTTP 服务，支持两种传输：
+//   POST /mcp                     Streamable HTTP，请求体为 JSON-RPC 消息，直接返回 JSON 响应
+//   GET /sse + POST /messages     旧版 HTTP+SSE，响应通过 SSE 流推送
+// 每个请求都需要携带 Authorization: Bearer <token>。
+//
+// 外部 agent 以 `chatshell --mcp-stdio` 启动时，进程不打开窗口，而是作为 stdio 传输的
+// MCP 服务运行，把请求转发给正在运行的应用，终端会话始终只存在于应用进程中。
+//
+// 端口、token 和是否启用保存在应用配置目录的 mcp_server.json 中（权限 0600）。
+
+use crate::ai::TerminalMCPServer;
+use crate::error::{AppError, AppResult};
+use crate::policy::{self, ApprovalContext, ApprovalPolicy};
+use crate::profiles::PROFILES;
+use crate::secrets;
+use async_trait::async_trait;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use once_cell::sync::Lazy;
+use rust_mcp_schema::schema_utils::CallToolError;
+use rust_mcp_schema::{
+    CallToolRequest, CallToolResult, Implementation, InitializeResult, ListToolsRequest, ListToolsResult, RpcError,
+    ServerCapabilities, ServerCapabilitiesTools, LATEST_PROTOCOL_VERSION,
+};
+use rust_mcp_sdk::mcp_server::{server_runtime, ServerHandler};
+use rust_mcp_sdk::{MCPServer, StdioTransport, TransportOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::AppHandle;
+use tokio::sync::{mpsc, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+const SETTINGS_FILE: &str = "mcp_server.json";
+const DEFAULT_PORT: u16 = 17321;
+const SERVER_NAME: &str = "chatshell";
+
+// 与 tauri.conf.json 中的 identifier 一致，stdio 模式下用于定位应用配置目录
+const APP_IDENTIFIER: &str = "com.chatshell.app";
+
+const JSONRPC_METHOD_NOT_FOUND: i64 = -32601;
+const JSONRPC_INVALID_PARAMS: i64 = -32602;
+const JSONRPC_INVALID_REQUEST: i64 = -32600;
+
+// 可以协商的协议版本：/mcp 按 2025-03-26 的 Streamable HTTP 处理，/sse 按 2024-11-05，
+// 工具相关的消息在两个版本中相同
+const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-03-26", LATEST_PROTOCOL_VERSION];
+
+// 每个 SSE 连接同时处理的请求上限，超出时直接拒绝
+const MAX_SSE_IN_FLIGHT: usize = 8;
+
+const INSTRUCTIONS: &str = "Tools for driving the user's chatshell terminals. \
+Commands run in the user's real shell; tools accept an optional session_id (see list_sessions) and default to the active terminal. \
+Commands that may change the system need the user's approval in chatshell.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpServerSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub token: String,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+impl Default for McpServerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            token: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct McpServerStatus {
+    pub enabled: bool,
+    pub running: bool,
+    pub port: u16,
+    pub url: String,
+    pub sse_url: String,
+    pub token: String,
+}
+
+struct RunningServer {
+    port: u16,
+    shutdown: CancellationToken,
+}
+
+struct ServerManager {
+    dir: Option<PathBuf>,
+    settings: McpServerSettings,
+    app_handle: Option<AppHandle>,
+    running: Option<RunningServer>,
+}
+
+static SERVER: Lazy<Mutex<ServerManager>> = Lazy::new(|| {
+    Mutex::new(ServerManager {
+        dir: None,
+        settings: McpServerSettings::default(),
+        app_handle: None,
+        running: None,
+    })
+});
+
+fn generate_token() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+fn load_settings(dir: &Path) -> AppResult<McpServerSettings> {
+    let path = dir.join(SETTINGS_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| AppError::config_invalid(format!("Failed to parse {}: {}", path.display(), e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(McpServerSettings::default()),
+        Err(e) => Err(AppError::io(format!("Failed to read {}: {}", path.display(), e))),
+    }
+}
+
+impl ServerManager {
+    fn save(&self) -> AppResult<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let content = serde_json::to_vec_pretty(&self.settings).map_err(|e| AppError::io(e.to_string()))?;
+        secrets::write_private(&dir.join(SETTINGS_FILE), &content).map_err(AppError::io)
+    }
+
+    fn status(&self) -> McpServerStatus {
+        let port = self.running.as_ref().map(|r| r.port).unwrap_or(self.settings.port);
+        McpServerStatus {
+            enabled: self.settings.enabled,
+            running: self.running.is_some(),
+            port,
+            url: format!("http://127.0.0.1:{}/mcp", port),
+            sse_url: format!("http://127.0.0.1:{}/sse", port),
+            token: self.settings.token.clone(),
+        }
+    }
+}
+
+// 应用启动时调用：载入设置，启用时启动服务
+pub fn init(app_handle: AppHandle, dir: PathBuf) -> AppResult<()> {
+    let settings = load_settings(&dir)?;
+    let enabled = settings.enabled;
+    {
+        let mut manager = SERVER.lock().unwrap();
+        manager.dir = Some(dir);
+        manager.settings = settings;
+        manager.app_handle = Some(app_handle);
+    }
+    if enabled {
+        tauri::async_runtime::spawn(async {
+            if let Err(e) = start().await {
+                eprintln!("Failed to start MCP server: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn start() -> AppResult<McpServerStatus> {
+    let (port, state) = {
+        let mut manager = SERVER.lock().unwrap();
+        if manager.running.is_some() {
+            return Ok(manager.status());
+        }
+        if manager.settings.token.is_empty() {
+            manager.settings.token = generate_token();
+            manager.save()?;
+        }
+        let state = Arc::new(HttpState {
+            token: manager.settings.token.clone(),
+            app_handle: manager.app_handle.clone(),
+            sse_sessions: Mutex::new(HashMap::new()),
+        });
+        (manager.settings.port, state)
+    };
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+        .await
+        .map_err(|e| AppError::io(format!("Failed to listen on 127.0.0.1:{}: {}", port, e)))?;
+    let port = listener.local_addr().map(|a| a.port()).unwrap_or(port);
+
+    let router = Router::new()
+        .route("/mcp", post(handle_streamable_http))
+        .route("/sse", get(handle_sse))
+        .route("/messages", post(handle_sse_message))
+        .with_state(state);
+
+    let shutdown = CancellationToken::new();
+    let signal = shutdown.clone();
+    tokio::spawn(async move {
+        let result = axum::serve(listener, router)
+            .with_graceful_shutdown(async move { signal.cancelled().await })
+            .await;
+        if let Err(e) = result {
+            eprintln!("MCP server stopped: {}", e);
+        }
+    });
+    println!("[RUST] MCP server listening on http://127.0.0.1:{}", port);
+
+    let mut manager = SERVER.lock().unwrap();
+    manager.running = Some(RunningServer { port, shutdown });
+    Ok(manager.status())
+}
+
+fn stop() {
+    if let Some(running) = SERVER.lock().unwrap().running.take() {
+        running.shutdown.cancel();
+        println!("[RUST] MCP server stopped");
+    }
+}
+
+// ---------------------------------------------------------------------------
+// JSON-RPC 处理
+
+pub fn tools() -> Vec<Value> {
+    let session_id = json!({ "type": "string", "description": "Terminal session id from list_sessions; defaults to the active terminal" });
+    vec![
+        json!({
+            "name": "execute_command",
+            "description": "Run a shell command in a chatshell terminal, wait for it to finish and return its output and exit code. May require the user's approval.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "command": { "type": "string", "description": "The shell command line to run" },
+                    "session_id": session_id
+                },
+                "required": ["command"]
+            }
+        }),
+        json!({
+            "name": "read_screen",
+            "description": "Read the text currently visible on a terminal screen.",
+            "inputSchema": { "type": "object", "properties": { "session_id": session_id } }
+        }),
+        json!({
+            "name": "get_cwd",
+            "description": "Get the current working directory of a terminal.",
+            "inputSchema": { "type": "object", "properties": { "session_id": session_id } }
+        }),
+        json!({
+            "name": "list_files",
+            "description": "List the files in the current working directory of a terminal.",
+            "inputSchema": { "type": "object", "properties": { "session_id": session_id } }
+        }),
+        json!({
+            "name": "list_sessions",
+            "description": "List the open terminal sessions with their shell, working directory and running command.",
+            "inputSchema": { "type": "object", "properties": {} }
+        }),
+        json!({
+            "name": "get_last_command_output",
+            "description": "Get the command line, output and exit code of the last command that finished in a terminal.",
+            "inputSchema": { "type": "object", "properties": { "session_id": session_id } }
+        }),
+    ]
+}
+
+fn rpc_result(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn rpc_error(id: &Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn server_details() -> InitializeResult {
+    InitializeResult {
+        capabilities: ServerCapabilities {
+            tools: Some(ServerCapabilitiesTools { list_changed: Some(false) }),
+            experimental: None,
+            logging: None,
+            prompts: None,
+            resources: None,
+        },
+        instructions: Some(INSTRUCTIONS.to_string()),
+        meta: None,
+        protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+        server_info: Implementation {
+            name: SERVER_NAME.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        },
+    }
+}
+
+// 外部客户端执行命令同样需要经过当前配置的审批规则
+async fn call_tool(app_handle: Option<&AppHandle>, name: &str, arguments: &Value) -> AppResult<String> {
+    if !tools().iter().any(|tool| tool["name"] == name) {
+        return Err(AppError::invalid_argument(format!("Unknown tool: {}", name)));
+    }
+    let arguments = arguments.to_string();
+    if let Some((command, classification)) = TerminalMCPServer::proposed_command(name, &arguments) {
+        let approval: ApprovalPolicy = PROFILES.lock().unwrap()
+            .active()
+            .map(|profile| profile.config.approval.clone())
+            .unwrap_or_default();
+        let context = ApprovalContext {
+            app_handle,
+            request_id: None,
+            conversation_id: "mcp",
+        };
+        policy::authorize(&approval, &command, classification, context).await?;
+    }
+    TerminalMCPServer::call_tool(name, &arguments).await
+}
+
+// 处理一条消息，通知和客户端响应没有返回值
+async fn handle_message(app_handle: Option<&AppHandle>, message: Value) -> Option<Value> {
+    let method = message.get("method").and_then(|m| m.as_str())?;
+    let id = message.get("id").cloned()?;
+    let params = message.get("params").cloned().unwrap_or_else(|| json!({}));
+
+    let response = match method {
+        "initialize" => {
+            let mut details = server_details();
+            // 支持客户端请求的版本时沿用该版本，否则返回最新版本，由客户端决定是否继续
+            if let Some(version) = params.get("protocolVersion").and_then(|v| v.as_str())
+                .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+            {
+                details.protocol_version = version.to_string();
+            }
+            match serde_json::to_value(details) {
+                Ok(result) => rpc_result(&id, result),
+                Err(e) => rpc_error(&id, JSONRPC_INVALID_REQUEST, &e.to_string()),
+            }
+        }
+        "ping" => rpc_result(&id, json!({})),
+        "tools/list" => rpc_result(&id, json!({ "tools": tools() })),
+        "tools/call" => {
+            let Some(name) = params.get("name").and_then(|n| n.as_str()) else {
+                return Some(rpc_error(&id, JSONRPC_INVALID_PARAMS, "Missing tool name"));
+            };
+            let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+            println!("[RUST] MCP client calls {} with {}", name, arguments);
+            // 工具执行失败作为结果返回给模型，而不是协议错误
+            let (text, is_error) = match call_tool(app_handle, name, &arguments).await {
+                Ok(output) => (output, false),
+                Err(e) => (e.to_string(), true),
+            };
+            rpc_result(&id, json!({ "content": [{ "type": "text", "text": text }], "isError": is_error }))
+        }
+        _ => rpc_error(&id, JSONRPC_METHOD_NOT_FOUND, &format!("Method not found: {}", method)),
+    };
+    Some(response)
+}
+
+// ---------------------------------------------------------------------------
+// HTTP 传输
+
+struct HttpState {
+    token: String,
+    app_handle: Option<AppHandle>,
+    // SSE 连接 ID -> 推送响应的通道
+    sse_sessions: Mutex<HashMap<String, SseSession>>,
+}
+
+struct SseSession {
+    sender: mpsc::UnboundedSender<Value>,
+    in_flight: Arc<Semaphore>,
+}
+
+// 只接受本机页面和应用自身的 Origin，按解析出的主机精确比较，允许带端口
+fn is_local_origin(origin: &str) -> bool {
+    let url = match reqwest::Url::parse(origin) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    match (url.scheme(), url.host_str()) {
+        ("http", Some(host)) => host == "127.0.0.1" || host == "localhost",
+        ("tauri", Some(host)) => host == "localhost",
+        _ => false,
+    }
+}
+
+// 校验 token，并拒绝来自非本地网页的请求（防止 DNS rebinding）
+fn authorize_request(state: &HttpState, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    if let Some(origin) = headers.get("origin") {
+        if !origin.to_str().is_ok_and(is_local_origin) {
+            return Err((StatusCode::FORBIDDEN, "Origin not allowed"));
+        }
+    }
+    let token = headers.get("authorization")
+        .and_then(|a| a.to_str().ok())
+        .and_then(|a| a.strip_prefix("Bearer "));
+    match token {
+        Some(token) if constant_time_eq(token.as_bytes(), state.token.as_bytes()) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "Missing or invalid token")),
+    }
+}
+
+// 比较耗时与 token 内容无关，避免通过响应时间逐字节猜测
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// 支持单条消息和批量消息
+async fn handle_payload(app_handle: Option<&AppHandle>, payload: Value) -> Option<Value> {
+    match payload {
+        Value::Array(messages) => {
+            let mut responses = Vec::new();
+            for message in messages {
+                if let Some(response) = handle_message(app_handle, message).await {
+                    responses.push(response);
+                }
+            }
+            if responses.is_empty() {
+                None
+            } else {
+                Some(Value::Array(responses))
+            }
+        }
+        message => handle_message(app_handle, message).await,
+    }
+}
+
+async fn handle_streamable_http(State(state): State<Arc<HttpState>>, headers: HeaderMap, Json(payload): Json<Value>) -> Response {
+    if let Err(rejection) = authorize_request(&state, &headers) {
+        return rejection.into_response();
+    }
+    match handle_payload(state.app_handle.as_ref(), payload).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+async fn handle_sse(State(state): State<Arc<HttpState>>, headers: HeaderMap) -> Response {
+    if let Err(rejection) = authorize_request(&state, &headers) {
+        return rejection.into_response();
+    }
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let (sender, receiver) = mpsc::unbounded_channel();
+    state.sse_sessions.lock().unwrap().insert(session_id.clone(), SseSession {
+        sender,
+        in_flight: Arc::new(Semaphore::new(MAX_SSE_IN_FLIGHT)),
+    });
+    println!("[RUST] MCP SSE client connected: {}", session_id);
+
+    // 第一条事件告诉客户端消息的提交地址
+    let endpoint = Event::default().event("endpoint").data(format!("/messages?session_id={}", session_id));
+    let guard = SseSessionGuard {
+        state: state.clone(),
+        session_id,
+    };
+    let messages = futures_util::stream::unfold((receiver, guard), |(mut receiver, guard)| async move {
+        let message = receiver.recv().await?;
+        let event = Event::default().event("message").data(message.to_string());
+        Some((Ok::<_, Infallible>(event), (receiver, guard)))
+    });
+    let stream = futures_util::StreamExt::chain(
+        futures_util::stream::once(async move { Ok::<_, Infallible>(endpoint) }),
+        messages,
+    );
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+// 连接断开时移除会话
+struct SseSessionGuard {
+    state: Arc<HttpState>,
+    session_id: String,
+}
+
+impl Drop for SseSessionGuard {
+    fn drop(&mut self) {
+        self.state.sse_sessions.lock().unwrap().remove(&self.session_id);
+        println!("[RUST] MCP SSE client disconnected: {}", self.session_id);
+    }
+}
+
+#[derive(Deserialize)]
+struct SseMessageQuery {
+    session_id: String,
+}
+
+async fn handle_sse_message(
+    State(state): State<Arc<HttpState>>,
+    Query(query): Query<SseMessageQuery>,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> Response {
+    if let Err(rejection) = authorize_request(&state, &headers) {
+        return rejection.into_response();
+    }
+    let (sender, in_flight) = match state.sse_sessions.lock().unwrap().get(&query.session_id) {
+        Some(session) => (session.sender.clone(), session.in_flight.clone()),
+        None => return (StatusCode::NOT_FOUND, "Unknown session").into_response(),
+    };
+    let permit = match in_flight.try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => return (StatusCode::TOO_MANY_REQUESTS, "Too many pending requests").into_response(),
+    };
+    // 工具调用可能等待用户审批，放到后台处理，结果通过 SSE 返回
+    tokio::spawn(async move {
+        if let Some(response) = handle_payload(state.app_handle.as_ref(), payload).await {
+            let _ = sender.send(response);
+        }
+        drop(permit);
+    });
+    StatusCode::ACCEPTED.into_response()
+}
+
+// ---------------------------------------------------------------------------
+// stdio 传输：把请求转发给正在运行的应用
+
+struct StdioBridge {
+    client: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+impl StdioBridge {
+    async fn forward(&self, method: &str, params: Value) -> Result<Value, String> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = self.client
+            .post(&self.url)
+            .bearer_auth(&self.token)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| format!("chatshell is not reachable at {}: {}", self.url, e))?;
+        if !response.status().is_success() {
+            return Err(format!("chatshell returned HTTP {}", response.status()));
+        }
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        if let Some(error) = body.get("error") {
+            return Err(error.get("message").and_then(|m| m.as_str()).unwrap_or("Unknown error").to_string());
+        }
+        body.get("result").cloned().ok_or_else(|| "Invalid response from chatshell".to_string())
+    }
+}
+
+#[async_trait]
+impl ServerHandler for StdioBridge {
+    async fn handle_list_tools_request(
+        &self,
+        _request: ListToolsRequest,
+        _runtime: &dyn MCPServer,
+    ) -> Result<ListToolsResult, RpcError> {
+        let result = self.forward("tools/list", json!({}))
+            .await
+            .map_err(|e| RpcError::internal_error().with_message(e))?;
+        serde_json::from_value(result).map_err(|e| RpcError::internal_error().with_message(e.to_string()))
+    }
+
+    async fn handle_call_tool_request(
+        &self,
+        request: CallToolRequest,
+        _runtime: &dyn MCPServer,
+    ) -> Result<CallToolResult, CallToolError> {
+        let params = serde_json::to_value(&request.params).map_err(CallToolError::new)?;
+        let result = self.forward("tools/call", params)
+            .await
+            .map_err(|e| CallToolError::new(std::io::Error::other(e)))?;
+        serde_json::from_value(result).map_err(CallToolError::new)
+    }
+}
+
+// `chatshell --mcp-stdio`：连接地址优先取环境变量，否则读取应用保存的设置
+pub async fn serve_stdio() -> AppResult<()> {
+    let settings = dirs::config_dir()
+        .map(|dir| dir.join(APP_IDENTIFIER))
+        .map(|dir| load_settings(&dir))
+        .transpose()?
+        .unwrap_or_default();
+    let url = std::env::var("CHATSHELL_MCP_URL")
+        .unwrap_or_else(|_| format!("http://127.0.0.1:{}/mcp", settings.port));
+    let token = std::env::var("CHATSHELL_MCP_TOKEN").unwrap_or(settings.token);
+    if token.is_empty() {
+        return Err(AppError::config_invalid("MCP server is not enabled in chatshell"));
+    }
+
+    let bridge = StdioBridge {
+        client: reqwest::Client::new(),
+        url,
+        token,
+    };
+    let transport = StdioTransport::new(TransportOptions::default()).map_err(|e| AppError::io(e.to_string()))?;
+    let server = server_runtime::create_server(server_details(), transport, bridge);
+    server.start().await.map_err(|e| AppError::io(e.to_string()))
+}
+
+// Tauri 命令
+#[tauri::command]
+pub async fn get_mcp_server_status() -> AppResult<McpServerStatus> {
+    Ok(SERVER.lock().unwrap().status())
+}
+
+#[tauri::command]
+pub async fn set_mcp_server_enabled(enabled: bool, port: Option<u16>) -> AppResult<McpServerStatus> {
+    {
+        let mut manager = SERVER.lock().unwrap();
+        let port_changed = port.is_some_and(|p| p != manager.settings.port);
+        manager.settings.enabled = enabled;
+        if let Some(port) = port {
+            manager.settings.port = port;
+        }
+        manager.save()?;
+        if !port_changed && (enabled == manager.running.is_some()) {
+            return Ok(manager.status());
+        }
+    }
+    stop();
+    if enabled {
+        start().await
+    } else {
+        Ok(SERVER.lock().unwrap().status())
+    }
+}
+
+// 重新生成 token，已配置的客户端需要更新
+#[tauri::command]
+pub async fn regenerate_mcp_token() -> AppResult<McpServerStatus> {
+    let running = {
+        let mut manager = SERVER.lock().unwrap();
+        manager.settings.token = generate_token();
+        manager.save()?;
+        manager.running.is_some()
+    };
+    if running {
+        stop();
+        start().await
+    } else {
+        Ok(SERVER.lock().unwrap().status())
+    }
+}
@@ -0,0 +1,219 @@
+// src/plugins/dispatch.rs - 每个会话一个插件分发任务
+//
+// 插件实例归分发任务所有，会话只保存发送端。终端输出、命令开始/结束等事件通过有界队列
+// 投递，队列将满时丢弃输出事件，读取 PTY 的线程不会等待插件；before_command 以及
+// 加载/卸载插件走单独的队列并优先处理。每次调用钩子都有超时，慢插件只会丢失事件。
+
+use crate::commands::{CommandAction, TerminalPlugin};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+const EVENT_QUEUE_SIZE: usize = 1024;
+// 为输出以外的事件保留的队列容量
+const RESERVED_CAPACITY: usize = 64;
+// 单个插件处理一个事件的时间上限
+pub(super) const HOOK_TIMEOUT: Duration = Duration::from_secs(1);
+// 等待所有插件处理 before_command 的时间上限
+const BEFORE_COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub type BoxedPlugin = Box<dyn TerminalPlugin + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub enum PluginEvent {
+    CommandStart(String),
+    Output(String),
+    CommandEnd(Option<i32>),
+    CwdChange(String),
+}
+
+enum PluginRequest {
+    BeforeCommand {
+        command: String,
+        reply: oneshot::Sender<CommandAction>,
+    },
+    Add(BoxedPlugin),
+    Remove(String),
+}
+
+// 会话持有的发送端，会话删除后分发任务通知插件会话结束并退出
+#[derive(Clone)]
+pub struct PluginDispatcher {
+    session_id: String,
+    events: mpsc::Sender<PluginEvent>,
+    requests: mpsc::UnboundedSender<PluginRequest>,
+    // 已加载的插件名，与分发任务中的实例保持一致
+    names: Arc<Mutex<Vec<String>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl PluginDispatcher {
+    pub fn spawn(session_id: &str, plugins: Vec<BoxedPlugin>) -> Self {
+        let (events, event_rx) = mpsc::channel(EVENT_QUEUE_SIZE);
+        let (requests, request_rx) = mpsc::unbounded_channel();
+        let names = plugins.iter().map(|p| p.name().to_string()).collect();
+        tokio::spawn(run(session_id.to_string(), plugins, event_rx, request_rx));
+        Self {
+            session_id: session_id.to_string(),
+            events,
+            requests,
+            names: Arc::new(Mutex::new(names)),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.names.lock().unwrap().clone()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.lock().unwrap().iter().any(|n| n == name)
+    }
+
+    pub fn add(&self, plugin: BoxedPlugin) {
+        self.names.lock().unwrap().push(plugin.name().to_string());
+        let _ = self.requests.send(PluginRequest::Add(plugin));
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.names.lock().unwrap().retain(|n| n != name);
+        let _ = self.requests.send(PluginRequest::Remove(name.to_string()));
+    }
+
+    // 不等待插件处理，队列满时丢弃
+    pub fn send(&self, event: PluginEvent) {
+        let full = matches!(event, PluginEvent::Output(_)) && self.events.capacity() <= RESERVED_CAPACITY;
+        if full || self.events.try_send(event).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if dropped == 1 || dropped.is_multiple_of(1000) {
+                eprintln!("Plugins in session {} are not keeping up, dropped {} events", self.session_id, dropped);
+            }
+        }
+    }
+
+    // 依次交给插件的 before_command 处理，超时按 Continue 处理
+    pub async fn before_command(&self, command: &str) -> CommandAction {
+        let (reply, receiver) = oneshot::channel();
+        let request = PluginRequest::BeforeCommand {
+            command: command.to_string(),
+            reply,
+        };
+        if self.requests.send(request).is_err() {
+            return CommandAction::Continue;
+        }
+        match tokio::time::timeout(BEFORE_COMMAND_TIMEOUT, receiver).await {
+            Ok(Ok(action)) => action,
+            Ok(Err(_)) => CommandAction::Continue,
+            Err(_) => {
+                eprintln!("Plugins in session {} timed out in before_command: {}", self.session_id, command);
+                CommandAction::Continue
+            }
+        }
+    }
+}
+
+async fn with_timeout<T>(plugin: &str, hook: &str, future: impl Future<Output = T>) -> Option<T> {
+    match tokio::time::timeout(HOOK_TIMEOUT, future).await {
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("Plugin {} timed out in {}", plugin, hook);
+            None
+        }
+    }
+}
+
+async fn run(
+    session_id: String,
+    mut plugins: Vec<BoxedPlugin>,
+    mut events: mpsc::Receiver<PluginEvent>,
+    mut requests: mpsc::UnboundedReceiver<PluginRequest>,
+) {
+    for plugin in &mut plugins {
+        let name = plugin.name().to_string();
+        with_timeout(&name, "on_session_start", plugin.on_session_start(&session_id)).await;
+    }
+
+    loop {
+        tokio::select! {
+            biased;
+            request = requests.recv() => match request {
+                Some(request) => handle_request(&session_id, &mut plugins, request).await,
+                None => break,
+            },
+            event = events.recv() => match event {
+                Some(event) => handle_event(&session_id, &mut plugins, event).await,
+                None => break,
+            },
+        }
+    }
+
+    for plugin in &mut plugins {
+        let name = plugin.name().to_string();
+        with_timeout(&name, "on_session_end", plugin.on_session_end(&session_id)).await;
+    }
+}
+
+async fn handle_request(session_id: &str, plugins: &mut Vec<BoxedPlugin>, request: PluginRequest) {
+    match request {
+        PluginRequest::BeforeCommand { command, reply } => {
+            let action = before_command(session_id, plugins, &command).await;
+            let _ = reply.send(action);
+        }
+        PluginRequest::Add(mut plugin) => {
+            let name = plugin.name().to_string();
+            with_timeout(&name, "on_session_start", plugin.on_session_start(session_id)).await;
+            plugins.push(plugin);
+        }
+        PluginRequest::Remove(name) => {
+            if let Some(index) = plugins.iter().position(|p| p.name() == name) {
+                let mut plugin = plugins.remove(index);
+                with_timeout(&name, "on_session_end", plugin.on_session_end(session_id)).await;
+            }
+        }
+    }
+}
+
+async fn before_command(session_id: &str, plugins: &mut [BoxedPlugin], command: &str) -> CommandAction {
+    let mut current = command.to_string();
+    for plugin in plugins.iter_mut() {
+        let name = plugin.name().to_string();
+        let action = with_timeout(&name, "before_command", plugin.before_command(&current, session_id))
+            .await
+            .unwrap_or(CommandAction::Continue);
+        match action {
+            CommandAction::Continue => {}
+            CommandAction::Rewrite(rewritten) => {
+                println!("[RUST] Plugin {} rewrote command: {} -> {}", name, current, rewritten);
+                current = rewritten;
+            }
+            action => return action,
+        }
+    }
+    if current == command {
+        CommandAction::Continue
+    } else {
+        CommandAction::Rewrite(current)
+    }
+}
+
+async fn handle_event(session_id: &str, plugins: &mut [BoxedPlugin], event: PluginEvent) {
+    for plugin in plugins.iter_mut() {
+        let name = plugin.name().to_string();
+        match &event {
+            PluginEvent::CommandStart(command) => {
+                with_timeout(&name, "on_command_start", plugin.on_command_start(command, session_id)).await;
+            }
+            PluginEvent::Output(output) => {
+                with_timeout(&name, "on_output", plugin.on_output(output, session_id)).await;
+            }
+            PluginEvent::CommandEnd(exit_code) => {
+                with_timeout(&name, "on_command_end", plugin.on_command_end(*exit_code, session_id)).await;
+            }
+            PluginEvent::CwdChange(cwd) => {
+                with_timeout(&name, "on_cwd_change", plugin.on_cwd_change(cwd, session_id)).await;
+            }
+        }
+    }
+}
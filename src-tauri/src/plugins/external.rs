@@ -0,0 +1,351 @@
+// src/plugins/external.rs - 外部进程插件，可以用 Python、Node 等任意语言编写
+//
+// 插件列表保存在应用配置目录的 external_plugins.json 中：
+//   [{ "name": "notes", "command": "python3", "args": ["/path/to/plugin.py"], "timeout_ms": 500 }]
+// 每个插件启动一个进程，所有会话共用，通过 stdin/stdout 逐行交换 JSON-RPC 2.0 消息。
+//
+// 宿主发送的通知，params 中都带 session_id：
+//   session_start, session_end, command_start {command}, output {output},
+//   command_end {exit_code}, cwd_change {cwd}
+// 宿主发送的请求：
+//   before_command {command} -> {"action": "continue" | "rewrite" | "swallow" | "respond", "command" | "text": ...}
+// 插件发送的通知：
+//   notify {session_id, message}   在前端显示提示
+//
+// 通知放入有界队列由单独的线程写入，插件处理不过来时丢弃，不会阻塞终端输出；
+// 请求超过 timeout_ms 没有回复时按 continue 处理。进程退出后在下一个事件时重新启动，
+// 两次启动至少间隔 RESTART_DELAY。stdin 关闭表示应用退出，插件应随之退出。
+
+use super::{emit_notice, ActionResponse, PLUGIN_REGISTRY};
+use crate::commands::{CommandAction, TerminalPlugin};
+use crate::error::{AppError, AppResult};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tokio::sync::oneshot;
+
+const PLUGINS_FILE: &str = "external_plugins.json";
+const DEFAULT_TIMEOUT_MS: u64 = 500;
+// 等待写入插件 stdin 的消息数
+const QUEUE_SIZE: usize = 256;
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExternalPluginConfig {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    // 未在插件设置中单独开关时是否对新会话启用
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // before_command 等待回复的时间
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    // 转发的事件，未指定时全部转发
+    #[serde(default)]
+    pub hooks: Option<Vec<String>>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_TIMEOUT_MS
+}
+
+#[derive(Default)]
+struct ProcessState {
+    child: Option<Child>,
+    tx: Option<SyncSender<String>>,
+    started_at: Option<Instant>,
+}
+
+type Reply = Result<Value, String>;
+
+// 一个插件进程，由所有会话的插件实例共享
+struct ExternalProcess {
+    config: ExternalPluginConfig,
+    app_handle: AppHandle,
+    state: Mutex<ProcessState>,
+    pending: Mutex<HashMap<u64, oneshot::Sender<Reply>>>,
+    next_id: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl ExternalProcess {
+    fn wants(&self, hook: &str) -> bool {
+        self.config.hooks.as_ref().is_none_or(|hooks| hooks.iter().any(|h| h == hook))
+    }
+
+    // 返回写入队列，进程没有运行时重新启动
+    fn sender(self: &Arc<Self>) -> Option<SyncSender<String>> {
+        let mut state = self.state.lock().unwrap();
+        let running = match state.child.as_mut() {
+            Some(child) => matches!(child.try_wait(), Ok(None)),
+            None => false,
+        };
+        if running {
+            return state.tx.clone();
+        }
+        if let Some(mut child) = state.child.take() {
+            eprintln!("External plugin {} exited: {:?}", self.config.name, child.wait().ok());
+        }
+        state.tx = None;
+        if state.started_at.is_some_and(|started| started.elapsed() < RESTART_DELAY) {
+            return None;
+        }
+
+        state.started_at = Some(Instant::now());
+        match self.spawn() {
+            Ok((child, tx)) => {
+                println!("[RUST] Started external plugin {} (pid {})", self.config.name, child.id());
+                state.child = Some(child);
+                state.tx = Some(tx.clone());
+                Some(tx)
+            }
+            Err(e) => {
+                eprintln!("Failed to start external plugin {}: {}", self.config.name, e);
+                None
+            }
+        }
+    }
+
+    fn spawn(self: &Arc<Self>) -> std::io::Result<(Child, SyncSender<String>)> {
+        let mut child = Command::new(&self.config.command)
+            .args(&self.config.args)
+            .envs(&self.config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        let (tx, rx) = mpsc::sync_channel::<String>(QUEUE_SIZE);
+        std::thread::spawn(move || {
+            for line in rx {
+                if stdin.write_all(line.as_bytes()).and_then(|_| stdin.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let process = self.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) if line.trim().is_empty() => {}
+                    Ok(line) => process.handle_message(&line),
+                    Err(_) => break,
+                }
+            }
+            // 等待中的请求立即返回
+            process.pending.lock().unwrap().clear();
+        });
+
+        let name = self.config.name.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                eprintln!("[{}] {}", name, line);
+            }
+        });
+
+        Ok((child, tx))
+    }
+
+    fn handle_message(&self, line: &str) {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => {
+                eprintln!("Invalid message from external plugin {}: {}", self.config.name, line);
+                return;
+            }
+        };
+
+        if let Some(method) = message.get("method").and_then(Value::as_str) {
+            match method {
+                "notify" => {
+                    let params = &message["params"];
+                    let session_id = params["session_id"].as_str().unwrap_or_default();
+                    let text = params["message"].as_str().unwrap_or_default().to_string();
+                    emit_notice(&self.app_handle, &self.config.name, session_id, text);
+                }
+                _ => eprintln!("Unknown method from external plugin {}: {}", self.config.name, method),
+            }
+            return;
+        }
+
+        let id = match message.get("id").and_then(Value::as_u64) {
+            Some(id) => id,
+            None => return,
+        };
+        // 超时后到达的回复直接丢弃
+        if let Some(waiter) = self.pending.lock().unwrap().remove(&id) {
+            let reply = match message.get("error") {
+                Some(error) => Err(error["message"].as_str().map(str::to_string).unwrap_or_else(|| error.to_string())),
+                None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+            };
+            let _ = waiter.send(reply);
+        }
+    }
+
+    // 不等待写入，队列满时丢弃
+    fn enqueue(&self, tx: &SyncSender<String>, message: Value) -> bool {
+        let mut line = message.to_string();
+        line.push('\n');
+        match tx.try_send(line) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped == 1 || dropped.is_multiple_of(1000) {
+                    eprintln!("External plugin {} is not keeping up, dropped {} messages", self.config.name, dropped);
+                }
+                false
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    fn notify(self: &Arc<Self>, method: &str, params: Value) {
+        if !self.wants(method) {
+            return;
+        }
+        if let Some(tx) = self.sender() {
+            self.enqueue(&tx, json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+        }
+    }
+
+    // 超时、出错或进程不可用时返回 None
+    async fn request(self: &Arc<Self>, method: &str, params: Value) -> Option<Value> {
+        if !self.wants(method) {
+            return None;
+        }
+        let tx = self.sender()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (waiter, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, waiter);
+        if !self.enqueue(&tx, json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })) {
+            self.pending.lock().unwrap().remove(&id);
+            return None;
+        }
+
+        match tokio::time::timeout(Duration::from_millis(self.config.timeout_ms), rx).await {
+            Ok(Ok(Ok(result))) => Some(result),
+            Ok(Ok(Err(e))) => {
+                eprintln!("External plugin {} failed in {}: {}", self.config.name, method, e);
+                None
+            }
+            // 进程退出，等待被清空
+            Ok(Err(_)) => None,
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                eprintln!("External plugin {} timed out in {} after {}ms", self.config.name, method, self.config.timeout_ms);
+                None
+            }
+        }
+    }
+}
+
+// 会话中的插件实例，只转发事件
+pub struct ExternalPlugin {
+    process: Arc<ExternalProcess>,
+}
+
+#[async_trait]
+impl TerminalPlugin for ExternalPlugin {
+    fn name(&self) -> &str {
+        &self.process.config.name
+    }
+
+    async fn before_command(&mut self, command: &str, session_id: &str) -> CommandAction {
+        let result = self
+            .process
+            .request("before_command", json!({ "session_id": session_id, "command": command }))
+            .await;
+        match result {
+            None | Some(Value::Null) => CommandAction::Continue,
+            Some(result) => match serde_json::from_value::<ActionResponse>(result) {
+                Ok(response) => response.into(),
+                Err(e) => {
+                    eprintln!("Invalid before_command response from external plugin {}: {}", self.name(), e);
+                    CommandAction::Continue
+                }
+            },
+        }
+    }
+
+    async fn on_command_start(&mut self, command: &str, session_id: &str) {
+        self.process.notify("command_start", json!({ "session_id": session_id, "command": command }));
+    }
+
+    async fn on_output(&mut self, output: &str, session_id: &str) {
+        self.process.notify("output", json!({ "session_id": session_id, "output": output }));
+    }
+
+    async fn on_command_end(&mut self, exit_code: Option<i32>, session_id: &str) {
+        self.process.notify("command_end", json!({ "session_id": session_id, "exit_code": exit_code }));
+    }
+
+    async fn on_cwd_change(&mut self, cwd: &str, session_id: &str) {
+        self.process.notify("cwd_change", json!({ "session_id": session_id, "cwd": cwd }));
+    }
+
+    async fn on_session_start(&mut self, session_id: &str) {
+        self.process.notify("session_start", json!({ "session_id": session_id }));
+    }
+
+    async fn on_session_end(&mut self, session_id: &str) {
+        self.process.notify("session_end", json!({ "session_id": session_id }));
+    }
+}
+
+// 应用启动时调用：读取配置并注册插件，进程在第一个事件时启动
+pub fn init(app_handle: AppHandle, dir: &Path) -> AppResult<()> {
+    let path = dir.join(PLUGINS_FILE);
+    let configs: Vec<ExternalPluginConfig> = match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| AppError::config_invalid(format!("Failed to parse {}: {}", path.display(), e)))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(AppError::io(format!("Failed to read {}: {}", path.display(), e))),
+    };
+
+    let mut registry = PLUGIN_REGISTRY.lock().unwrap();
+    for config in configs {
+        if registry.get(&config.name).is_some() {
+            eprintln!("External plugin {} conflicts with an existing plugin, skipped", config.name);
+            continue;
+        }
+        let name = config.name.clone();
+        let description = config.description.clone()
+            .filter(|d| !d.is_empty())
+            .unwrap_or_else(|| format!("外部插件 {}", config.command));
+        let enabled = config.enabled;
+        let process = Arc::new(ExternalProcess {
+            config,
+            app_handle: app_handle.clone(),
+            state: Mutex::new(ProcessState::default()),
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            dropped: AtomicU64::new(0),
+        });
+        registry.register(&name, &description, enabled, move || Box::new(ExternalPlugin { process: process.clone() }));
+        println!("[RUST] Registered external plugin {}", name);
+    }
+    Ok(())
+}
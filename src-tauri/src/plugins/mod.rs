@@ -1,9 +1,75 @@
 // src/plugins/mod.rs - 插件模块示例
 
-use crate::commands::TerminalPlugin;
+use crate::commands::{ColorPlugin, CommandAction, HistoryPlugin, TerminalPlugin, TERMINAL_MANAGER};
+use crate::error::{AppError, AppResult};
+use crate::secrets;
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 use regex::Regex;
+use tauri::{AppHandle, Emitter};
+
+mod dispatch;
+mod external;
+mod wasm;
+
+pub use dispatch::{PluginDispatcher, PluginEvent};
+
+const SETTINGS_FILE: &str = "plugins.json";
+
+// 解析 doge <子命令> 的参数，不是该子命令时返回 None
+pub(crate) fn doge_args<'a>(command: &'a str, subcommand: &str) -> Option<&'a str> {
+    let mut words = command.trim().splitn(3, char::is_whitespace);
+    if words.next() != Some("doge") || words.next() != Some(subcommand) {
+        return None;
+    }
+    Some(words.next().unwrap_or("").trim())
+}
+
+// WASM 和外部插件对 before_command 的回复
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ActionResponse {
+    Continue,
+    Rewrite { command: String },
+    Swallow,
+    Respond { text: String },
+}
+
+impl From<ActionResponse> for CommandAction {
+    fn from(response: ActionResponse) -> Self {
+        match response {
+            ActionResponse::Continue => CommandAction::Continue,
+            ActionResponse::Rewrite { command } => CommandAction::Rewrite(command),
+            ActionResponse::Swallow => CommandAction::Swallow,
+            ActionResponse::Respond { text } => CommandAction::Respond(text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PluginNoticeEvent {
+    plugin: String,
+    session_id: String,
+    message: String,
+}
+
+// 插件发出的提示，由前端显示在对应会话的终端中
+fn emit_notice(app_handle: &AppHandle, plugin: &str, session_id: &str, message: String) {
+    println!("[RUST] Plugin {} notice: {}", plugin, message);
+    let event = PluginNoticeEvent {
+        plugin: plugin.to_string(),
+        session_id: session_id.to_string(),
+        message,
+    };
+    if let Err(e) = app_handle.emit("plugin-notice", &event) {
+        eprintln!("Failed to emit plugin notice: {}", e);
+    }
+}
 
 // 命令计时插件
 pub struct TimingPlugin {
@@ -25,18 +91,19 @@ impl TimingPlugin {
     }
 }
 
+#[async_trait]
 impl TerminalPlugin for TimingPlugin {
     fn name(&self) -> &str {
         "timing"
     }
 
-    fn on_command_start(&mut self, command: &str, session_id: &str) {
+    async fn on_command_start(&mut self, command: &str, session_id: &str) {
         let timestamp = Self::current_timestamp();
         self.command_start_times.insert(session_id.to_string(), timestamp);
         println!("🐶 [{}] Command started: {}", session_id, command);
     }
 
-    fn on_command_end(&mut self, exit_code: Option<i32>, session_id: &str) {
+    async fn on_command_end(&mut self, exit_code: Option<i32>, session_id: &str) {
         if let Some(start_time) = self.command_start_times.remove(session_id) {
             let duration = Self::current_timestamp() - start_time;
             let status = match exit_code {
@@ -65,10 +132,12 @@ impl GitPlugin {
         std::path::Path::new(".git").exists()
     }
 
-    fn get_git_branch() -> Option<String> {
-        std::process::Command::new("git")
-            .args(&["branch", "--show-current"])
+    // 异步执行 git，不占用插件分发任务所在的线程
+    async fn get_git_branch() -> Option<String> {
+        tokio::process::Command::new("git")
+            .args(["branch", "--show-current"])
             .output()
+            .await
             .ok()
             .and_then(|output| {
                 if output.status.success() {
@@ -83,23 +152,19 @@ impl GitPlugin {
     }
 }
 
+#[async_trait]
 impl TerminalPlugin for GitPlugin {
     fn name(&self) -> &str {
         "git"
     }
 
-    fn on_command_start(&mut self, command: &str, session_id: &str) {
+    async fn on_command_start(&mut self, command: &str, session_id: &str) {
         if self.git_regex.is_match(command) && Self::is_git_repo() {
-            if let Some(branch) = Self::get_git_branch() {
+            if let Some(branch) = Self::get_git_branch().await {
                 println!("🐶 [{}] Git command in branch: {}", session_id, branch);
             }
         }
     }
-
-    fn on_output(&mut self, output: &str, _session_id: &str) -> String {
-        // 可以在这里添加 Git 输出的颜色高亮
-        output.to_string()
-    }
 }
 
 // 自动补全插件
@@ -140,10 +205,25 @@ impl AutoCompletePlugin {
     }
 }
 
+#[async_trait]
 impl TerminalPlugin for AutoCompletePlugin {
     fn name(&self) -> &str {
         "autocomplete"
     }
+
+    // doge complete <前缀>
+    async fn before_command(&mut self, command: &str, _session_id: &str) -> CommandAction {
+        let prefix = match doge_args(command, "complete") {
+            Some(prefix) => prefix,
+            None => return CommandAction::Continue,
+        };
+        let suggestions = self.get_suggestions(prefix);
+        if suggestions.is_empty() {
+            CommandAction::Respond(format!("🐶 No suggestions for '{}'", prefix))
+        } else {
+            CommandAction::Respond(format!("🐶 Suggestions:\n{}", suggestions.join("\n")))
+        }
+    }
 }
 
 // 别名插件
@@ -175,7 +255,7 @@ impl AliasPlugin {
     }
 
     pub fn expand_alias(&self, command: &str) -> String {
-        let parts: Vec<&str> = command.trim().split_whitespace().collect();
+        let parts: Vec<&str> = command.split_whitespace().collect();
         if let Some(first_word) = parts.first() {
             if let Some(expanded) = self.aliases.get(*first_word) {
                 if parts.len() > 1 {
@@ -192,15 +272,37 @@ impl AliasPlugin {
     }
 }
 
+#[async_trait]
 impl TerminalPlugin for AliasPlugin {
     fn name(&self) -> &str {
         "alias"
     }
 
-    fn on_command_start(&mut self, command: &str, session_id: &str) {
+    // doge alias 列出别名，doge alias 名称=命令 添加别名，其余命令按别名展开
+    async fn before_command(&mut self, command: &str, _session_id: &str) -> CommandAction {
+        if let Some(args) = doge_args(command, "alias") {
+            if args.is_empty() {
+                let mut aliases: Vec<String> = self.aliases.iter()
+                    .map(|(alias, expanded)| format!("{}='{}'", alias, expanded))
+                    .collect();
+                aliases.sort();
+                return CommandAction::Respond(format!("🐶 Aliases:\n{}", aliases.join("\n")));
+            }
+            return match args.split_once('=') {
+                Some((alias, expanded)) if !alias.trim().is_empty() && !expanded.trim().is_empty() => {
+                    let expanded = expanded.trim().trim_matches(|c| c == '\'' || c == '"');
+                    self.add_alias(alias.trim(), expanded);
+                    CommandAction::Respond(format!("🐶 Alias added: {} -> {}", alias.trim(), expanded))
+                }
+                _ => CommandAction::Respond("🐶 Usage: doge alias [name=command]".to_string()),
+            };
+        }
+
         let expanded = self.expand_alias(command);
         if expanded != command {
-            println!("🐶 [{}] Alias expanded: {} -> {}", session_id, command, expanded);
+            CommandAction::Rewrite(expanded)
+        } else {
+            CommandAction::Continue
         }
     }
 }
@@ -287,22 +389,37 @@ impl ThemePlugin {
     }
 }
 
+#[async_trait]
 impl TerminalPlugin for ThemePlugin {
     fn name(&self) -> &str {
         "theme"
     }
 
-    fn on_command_start(&mut self, command: &str, session_id: &str) {
-        if command.starts_with("doge theme ") {
-            let theme_name = command.trim_start_matches("doge theme ").trim();
-            match self.set_theme(theme_name) {
-                Ok(()) => println!("🐶 [{}] Theme changed to: {}", session_id, theme_name),
-                Err(e) => println!("🐶 [{}] Theme error: {}", session_id, e),
+    // doge theme 显示当前主题，doge theme list 列出主题，doge theme <名称> 切换主题
+    async fn before_command(&mut self, command: &str, _session_id: &str) -> CommandAction {
+        let args = match doge_args(command, "theme") {
+            Some(args) => args,
+            None => return CommandAction::Continue,
+        };
+        let response = match args {
+            "" => match self.get_current_theme() {
+                Some(theme) => format!(
+                    "🐶 Current theme: {} (background {}, foreground {}, cursor {}, selection {})",
+                    self.current_theme, theme.background, theme.foreground, theme.cursor, theme.selection
+                ),
+                None => format!("🐶 Current theme: {}", self.current_theme),
+            },
+            "list" => {
+                let mut themes = self.list_themes();
+                themes.sort();
+                format!("🐶 Available themes: {}", themes.iter().map(|t| t.as_str()).collect::<Vec<_>>().join(", "))
             }
-        } else if command == "doge theme list" {
-            let themes = self.list_themes();
-            println!("🐶 [{}] Available themes: {:?}", session_id, themes);
-        }
+            name => match self.set_theme(name) {
+                Ok(()) => format!("🐶 Theme changed to: {}", name),
+                Err(e) => format!("🐶 Theme error: {}", e),
+            },
+        };
+        CommandAction::Respond(response)
     }
 }
 
@@ -345,25 +462,34 @@ impl MonitorPlugin {
     }
 }
 
+#[async_trait]
 impl TerminalPlugin for MonitorPlugin {
     fn name(&self) -> &str {
         "monitor"
     }
 
-    fn on_command_start(&mut self, command: &str, session_id: &str) {
-        if command == "doge monitor on" {
-            self.show_system_info = true;
-            println!("🐶 [{}] System monitoring enabled", session_id);
-        } else if command == "doge monitor off" {
-            self.show_system_info = false;
-            println!("🐶 [{}] System monitoring disabled", session_id);
-        } else if command == "doge monitor status" {
-            let info = Self::get_system_info();
-            println!("🐶 [{}] System status: {}", session_id, info);
-        }
+    // doge monitor on|off|status
+    async fn before_command(&mut self, command: &str, _session_id: &str) -> CommandAction {
+        let args = match doge_args(command, "monitor") {
+            Some(args) => args,
+            None => return CommandAction::Continue,
+        };
+        let response = match args {
+            "on" => {
+                self.show_system_info = true;
+                "🐶 System monitoring enabled".to_string()
+            }
+            "off" => {
+                self.show_system_info = false;
+                "🐶 System monitoring disabled".to_string()
+            }
+            "" | "status" => format!("🐶 System status: {}", Self::get_system_info()),
+            _ => "🐶 Usage: doge monitor [on|off|status]".to_string(),
+        };
+        CommandAction::Respond(response)
     }
 
-    fn on_session_start(&mut self, session_id: &str) {
+    async fn on_session_start(&mut self, session_id: &str) {
         if self.show_system_info {
             let info = Self::get_system_info();
             println!("🐶 [{}] Welcome! System: {}", session_id, info);
@@ -371,68 +497,246 @@ impl TerminalPlugin for MonitorPlugin {
     }
 }
 
-// 插件管理器
-pub struct PluginManager {
-    plugins: Vec<Box<dyn TerminalPlugin + Send + Sync>>,
+// 插件注册表：每个插件对应一个工厂，新会话按配置创建各自的实例
+pub type PluginFactory = Arc<dyn Fn() -> Box<dyn TerminalPlugin + Send + Sync> + Send + Sync>;
+
+struct RegisteredPlugin {
+    name: String,
+    description: String,
+    // 未在设置中单独开关时是否对新会话启用
+    default_enabled: bool,
+    factory: PluginFactory,
+}
+
+// 保存在 plugins.json 中的开关，只记录与默认值不同的插件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PluginSettings {
+    #[serde(default)]
+    enabled: HashMap<String, bool>,
 }
 
-impl PluginManager {
-    pub fn new() -> Self {
-        Self {
-            plugins: Vec::new(),
-        }
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<RegisteredPlugin>,
+    settings: PluginSettings,
+    // 未初始化时只保存在内存中
+    path: Option<PathBuf>,
+}
+
+impl PluginRegistry {
+    fn with_builtin_plugins() -> Self {
+        let mut registry = Self::default();
+        registry.register("history", "记录会话中执行过的命令", true, || Box::new(HistoryPlugin::new()));
+        registry.register("color", "输出颜色处理", true, || Box::new(ColorPlugin));
+        registry.register("timing", "记录命令耗时和退出状态", false, || Box::new(TimingPlugin::new()));
+        registry.register("git", "在 Git 命令前显示当前分支", false, || Box::new(GitPlugin::new()));
+        registry.register("autocomplete", "常用命令补全建议", false, || Box::new(AutoCompletePlugin::new()));
+        registry.register("alias", "常用命令别名", false, || Box::new(AliasPlugin::new()));
+        registry.register("theme", "doge theme 切换终端主题", false, || Box::new(ThemePlugin::new()));
+        registry.register("monitor", "doge monitor 显示系统负载", false, || Box::new(MonitorPlugin::new()));
+        registry
+    }
+
+    // 同名插件会被替换
+    pub fn register<F>(&mut self, name: &str, description: &str, default_enabled: bool, factory: F)
+    where
+        F: Fn() -> Box<dyn TerminalPlugin + Send + Sync> + Send + Sync + 'static,
+    {
+        self.plugins.retain(|p| p.name != name);
+        self.plugins.push(RegisteredPlugin {
+            name: name.to_string(),
+            description: description.to_string(),
+            default_enabled,
+            factory: Arc::new(factory),
+        });
     }
 
-    pub fn register_default_plugins(&mut self) {
-        self.plugins.push(Box::new(TimingPlugin::new()));
-        self.plugins.push(Box::new(GitPlugin::new()));
-        self.plugins.push(Box::new(AutoCompletePlugin::new()));
-        self.plugins.push(Box::new(AliasPlugin::new()));
-        self.plugins.push(Box::new(ThemePlugin::new()));
-        self.plugins.push(Box::new(MonitorPlugin::new()));
+    fn unregister(&mut self, name: &str) {
+        self.plugins.retain(|p| p.name != name);
     }
 
-    pub fn add_plugin(&mut self, plugin: Box<dyn TerminalPlugin + Send + Sync>) {
-        self.plugins.push(plugin);
+    fn get(&self, name: &str) -> Option<&RegisteredPlugin> {
+        self.plugins.iter().find(|p| p.name == name)
     }
 
-    pub fn get_plugins(&self) -> &Vec<Box<dyn TerminalPlugin + Send + Sync>> {
-        &self.plugins
+    fn is_enabled(&self, plugin: &RegisteredPlugin) -> bool {
+        self.settings.enabled.get(&plugin.name).copied().unwrap_or(plugin.default_enabled)
     }
 
-    pub fn get_plugins_mut(&mut self) -> &mut Vec<Box<dyn TerminalPlugin + Send + Sync>> {
-        &mut self.plugins
+    pub fn create(&self, name: &str) -> Option<Box<dyn TerminalPlugin + Send + Sync>> {
+        self.get(name).map(|p| (p.factory)())
     }
 
-    pub fn list_plugins(&self) -> Vec<&str> {
-        self.plugins.iter().map(|p| p.name()).collect()
+    // 会话配置指定了插件列表时按列表创建，否则创建所有启用的插件
+    pub fn instantiate(&self, names: Option<&[String]>) -> Vec<Box<dyn TerminalPlugin + Send + Sync>> {
+        if let Some(names) = names {
+            for name in names.iter().filter(|name| self.get(name).is_none()) {
+                eprintln!("Unknown plugin in terminal config: {}", name);
+            }
+        }
+        self.plugins.iter()
+            .filter(|p| match names {
+                Some(names) => names.contains(&p.name),
+                None => self.is_enabled(p),
+            })
+            .map(|p| (p.factory)())
+            .collect()
     }
-}
 
-// 扩展的 Tauri 命令用于插件管理
-#[tauri::command]
-pub async fn list_plugins() -> Result<Vec<String>, String> {
-    let manager = crate::commands::TERMINAL_MANAGER.lock().unwrap();
-    if let Some(session_id) = manager.get_active_session() {
-        if let Some(session) = manager.sessions.get(session_id) {
-            let plugin_names: Vec<String> = session.plugins.iter()
-                .map(|p| p.name().to_string())
-                .collect();
-            Ok(plugin_names)
+    fn set_enabled(&mut self, name: &str, enabled: bool) -> AppResult<()> {
+        let plugin = self.get(name).ok_or_else(|| AppError::not_found("Plugin", name))?;
+        if enabled == plugin.default_enabled {
+            self.settings.enabled.remove(name);
         } else {
-            Err("No active session".to_string())
+            self.settings.enabled.insert(name.to_string(), enabled);
         }
+        self.save()
+    }
+
+    fn save(&self) -> AppResult<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let content = serde_json::to_string_pretty(&self.settings).map_err(|e| AppError::io(e.to_string()))?;
+        secrets::write_private(path, content.as_bytes()).map_err(AppError::io)
+    }
+}
+
+pub static PLUGIN_REGISTRY: Lazy<Mutex<PluginRegistry>> =
+    Lazy::new(|| Mutex::new(PluginRegistry::with_builtin_plugins()));
+
+// 应用启动时调用：载入插件开关、外部插件和插件目录中的 WASM 插件
+pub fn init(app_handle: AppHandle, dir: PathBuf) -> AppResult<()> {
+    let path = dir.join(SETTINGS_FILE);
+    let settings = match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| AppError::config_invalid(format!("Failed to parse {}: {}", path.display(), e)))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => PluginSettings::default(),
+        Err(e) => return Err(AppError::io(format!("Failed to read {}: {}", path.display(), e))),
+    };
+    {
+        let mut registry = PLUGIN_REGISTRY.lock().unwrap();
+        registry.settings = settings;
+        registry.path = Some(path);
+    }
+    if let Err(e) = external::init(app_handle.clone(), &dir) {
+        eprintln!("Failed to load external plugins: {}", e);
+    }
+    if let Err(e) = wasm::init(app_handle, dir) {
+        eprintln!("Failed to load WASM plugins: {}", e);
+    }
+    Ok(())
+}
+
+// 插件及其在会话中的状态
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub description: String,
+    // 新会话是否默认启用
+    pub enabled: bool,
+    // 是否已加载到指定（或活动）会话中，没有会话时为 None
+    pub active: Option<bool>,
+}
+
+// 在会话中加载或卸载插件，会话不存在时返回错误
+fn set_session_plugin(session_id: &str, name: &str, enabled: bool) -> AppResult<()> {
+    // 先创建实例再锁会话，避免与创建会话时的加锁顺序相反
+    let plugin = if enabled {
+        let registry = PLUGIN_REGISTRY.lock().unwrap();
+        Some(registry.create(name).ok_or_else(|| AppError::not_found("Plugin", name))?)
     } else {
-        Err("No active session".to_string())
+        None
+    };
+
+    let manager = TERMINAL_MANAGER.lock().unwrap();
+    let session = manager.get_session(session_id)
+        .ok_or_else(|| AppError::session_not_found(session_id))?;
+    let loaded = session.plugins.contains(name);
+    // 由会话的分发任务调用 on_session_start/on_session_end
+    match plugin {
+        Some(plugin) if !loaded => {
+            session.plugins.add(plugin);
+            println!("[RUST] Plugin {} enabled in session {}", name, session_id);
+        }
+        None if loaded => {
+            session.plugins.remove(name);
+            println!("[RUST] Plugin {} disabled in session {}", name, session_id);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// 指定会话时只作用于该会话；否则修改默认设置并应用到所有已打开的会话
+fn set_plugin_enabled(name: &str, enabled: bool, session_id: Option<&str>) -> AppResult<()> {
+    if let Some(session_id) = session_id {
+        return set_session_plugin(session_id, name, enabled);
+    }
+    PLUGIN_REGISTRY.lock().unwrap().set_enabled(name, enabled)?;
+    let session_ids: Vec<String> = TERMINAL_MANAGER.lock().unwrap().sessions().map(|s| s.id.clone()).collect();
+    for session_id in session_ids {
+        // 会话可能在此期间关闭
+        if let Err(e) = set_session_plugin(&session_id, name, enabled) {
+            eprintln!("Failed to update plugin {} in session {}: {}", name, session_id, e);
+        }
     }
+    Ok(())
 }
 
+// 扩展的 Tauri 命令用于插件管理
 #[tauri::command]
-pub async fn execute_plugin_command(command: String) -> Result<String, String> {
-    // 这里可以实现插件特定的命令执行逻辑
-    if command.starts_with("doge ") {
-        Ok(format!("🐶 Plugin command executed: {}", command))
-    } else {
-        Err("Not a plugin command".to_string())
+pub async fn list_plugins(session_id: Option<String>) -> AppResult<Vec<PluginInfo>> {
+    let loaded: Option<Vec<String>> = {
+        let manager = TERMINAL_MANAGER.lock().unwrap();
+        let session_id = session_id.as_ref().or(manager.get_active_session());
+        match session_id {
+            Some(id) => {
+                let session = manager.get_session(id).ok_or_else(|| AppError::session_not_found(id))?;
+                Some(session.plugins.names())
+            }
+            None => None,
+        }
+    };
+
+    let registry = PLUGIN_REGISTRY.lock().unwrap();
+    Ok(registry.plugins.iter()
+        .map(|p| PluginInfo {
+            name: p.name.clone(),
+            description: p.description.clone(),
+            enabled: registry.is_enabled(p),
+            active: loaded.as_ref().map(|names| names.contains(&p.name)),
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn enable_plugin(name: String, session_id: Option<String>) -> AppResult<()> {
+    set_plugin_enabled(&name, true, session_id.as_deref())
+}
+
+#[tauri::command]
+pub async fn disable_plugin(name: String, session_id: Option<String>) -> AppResult<()> {
+    set_plugin_enabled(&name, false, session_id.as_deref())
+}
+
+// 只交给会话中的插件处理，不会发送给 shell
+#[tauri::command]
+pub async fn execute_plugin_command(command: String, session_id: Option<String>) -> AppResult<String> {
+    let plugins = {
+        let manager = TERMINAL_MANAGER.lock().unwrap();
+        let session_id = session_id.or_else(|| manager.get_active_session().cloned())
+            .ok_or(AppError::NoActiveSession)?;
+        let session = manager.get_session(&session_id)
+            .ok_or_else(|| AppError::session_not_found(&session_id))?;
+        session.plugins.clone()
+    };
+    match plugins.before_command(&command).await {
+        CommandAction::Respond(response) => Ok(response),
+        CommandAction::Swallow => Ok(String::new()),
+        CommandAction::Continue | CommandAction::Rewrite(_) => {
+            Err(AppError::invalid_argument("Not a plugin command"))
+        }
     }
-}
\ No newline at end of file
+}
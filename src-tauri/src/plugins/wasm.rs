@@ -0,0 +1,627 @@
+// src/plugins/wasm.rs - WebAssembly 插件宿主
+//
+// 从应用配置目录的 plugins/ 下载入 *.wasm，注册到插件注册表，每个会话创建独立实例。
+// 目录中的文件新增、修改或删除后自动重新载入，已打开的会话换成新实例。
+//
+// 插件与宿主之间用 JSON 交换数据。字符串以 (ptr, len) 传入；返回值打包为 i64，
+// 高 32 位为指针、低 32 位为长度，0 表示没有返回值。
+//
+// 插件导出：
+//   memory
+//   alloc(len) -> ptr              宿主写入参数前调用，参数内存由插件释放
+//   dealloc(ptr, len)              可选，宿主读取返回值后调用
+//   plugin_info() -> i64           可选，{"name", "description", "default_enabled", "hooks"}
+//   handle_event(ptr, len) -> i64  参数为 {"hook": "before_command", "session_id": ..., ...}
+//     before_command 返回 {"action": "continue" | "rewrite" | "swallow" | "respond", "command" | "text": ...}
+//     其余钩子的返回值被忽略，output 钩子只收到输出副本，不能改写终端输出
+//
+// 宿主导入（模块 chatshell）：
+//   session_cwd() -> i64                    当前会话的工作目录
+//   notice(ptr, len)                        向前端发送提示
+//   query_history(ptr, len, limit) -> i64   按关键字查询本会话的历史命令
+//
+// WASI 只继承 stderr，不开放目录、环境变量和网络。调用在阻塞线程池中执行，
+// 超过钩子超时后由 epoch 中断；上一次调用未结束时跳过新的事件。
+
+use super::dispatch::HOOK_TIMEOUT;
+use super::{emit_notice, set_session_plugin, ActionResponse, PLUGIN_REGISTRY};
+use crate::commands::{CommandAction, TerminalPlugin, TERMINAL_MANAGER};
+use crate::error::{AppError, AppResult};
+use crate::storage;
+use async_trait::async_trait;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use tauri::AppHandle;
+use wasmtime::{
+    AsContext, AsContextMut, Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::WasiCtxBuilder;
+
+const PLUGIN_DIR: &str = "plugins";
+// 每次调用可用的燃料，大致对应执行的指令数，用完后调用被中止
+const FUEL_PER_CALL: u64 = 50_000_000;
+// 引擎计时间隔，调用超过钩子超时后在下一次计时时中断
+const EPOCH_TICK: Duration = Duration::from_millis(100);
+const EPOCH_DEADLINE: u64 = (HOOK_TIMEOUT.as_millis() / EPOCH_TICK.as_millis()) as u64;
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+// 等文件写完再重新载入
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(300);
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+const MAX_HISTORY_LIMIT: usize = 200;
+
+static WASM_HOST: Lazy<Mutex<Option<WasmHost>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct PluginManifest {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    default_enabled: bool,
+    // 订阅的钩子，未指定时全部订阅
+    #[serde(default)]
+    hooks: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "hook", rename_all = "snake_case")]
+enum HookEvent<'a> {
+    SessionStart,
+    SessionEnd,
+    BeforeCommand { command: &'a str },
+    CommandStart { command: &'a str },
+    Output { output: &'a str },
+    CommandEnd { exit_code: Option<i32> },
+    CwdChange { cwd: &'a str },
+}
+
+impl HookEvent<'_> {
+    fn hook(&self) -> &'static str {
+        match self {
+            HookEvent::SessionStart => "session_start",
+            HookEvent::SessionEnd => "session_end",
+            HookEvent::BeforeCommand { .. } => "before_command",
+            HookEvent::CommandStart { .. } => "command_start",
+            HookEvent::Output { .. } => "output",
+            HookEvent::CommandEnd { .. } => "command_end",
+            HookEvent::CwdChange { .. } => "cwd_change",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HookCall<'a> {
+    session_id: &'a str,
+    #[serde(flatten)]
+    event: HookEvent<'a>,
+}
+
+// query_history 返回给插件的字段，不包含命令输出
+#[derive(Serialize)]
+struct HistoryEntry {
+    command: String,
+    cwd: Option<String>,
+    exit_code: Option<i32>,
+    started_at: u64,
+}
+
+// 实例的宿主状态，宿主函数通过它读取会话信息
+struct HostState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+    plugin: String,
+    session_id: String,
+    app_handle: AppHandle,
+}
+
+fn wasm_error(e: wasmtime::Error) -> AppError {
+    AppError::from(format!("{:#}", e))
+}
+
+fn pack(ptr: i32, len: i32) -> i64 {
+    (((ptr as u32 as u64) << 32) | len as u32 as u64) as i64
+}
+
+fn unpack(value: i64) -> (i32, i32) {
+    let value = value as u64;
+    ((value >> 32) as u32 as i32, value as u32 as i32)
+}
+
+// 长度由插件给出，先检查是否在线性内存范围内再分配
+fn read_guest(ctx: impl AsContext, memory: Memory, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+    let start = ptr as u32 as usize;
+    let len = len as u32 as usize;
+    if start.checked_add(len).is_none_or(|end| end > memory.data_size(&ctx)) {
+        return Err(wasmtime::Error::msg("plugin passed a buffer outside its memory"));
+    }
+    let mut buf = vec![0u8; len];
+    memory.read(&ctx, start, &mut buf)?;
+    Ok(buf)
+}
+
+fn write_guest(mut ctx: impl AsContextMut, memory: Memory, alloc: &TypedFunc<i32, i32>, bytes: &[u8]) -> wasmtime::Result<(i32, i32)> {
+    let len = i32::try_from(bytes.len())?;
+    let ptr = alloc.call(&mut ctx, len)?;
+    memory.write(&mut ctx, ptr as u32 as usize, bytes)?;
+    Ok((ptr, len))
+}
+
+fn caller_memory(caller: &mut Caller<'_, HostState>) -> wasmtime::Result<Memory> {
+    caller.get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmtime::Error::msg("plugin does not export memory"))
+}
+
+fn read_caller_string(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> wasmtime::Result<String> {
+    let memory = caller_memory(caller)?;
+    let bytes = read_guest(&*caller, memory, ptr, len)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// 由插件分配内存写入返回值，空内容返回 0
+fn return_bytes(caller: &mut Caller<'_, HostState>, bytes: &[u8]) -> wasmtime::Result<i64> {
+    if bytes.is_empty() {
+        return Ok(0);
+    }
+    let memory = caller_memory(caller)?;
+    let alloc = caller.get_export("alloc")
+        .and_then(Extern::into_func)
+        .ok_or_else(|| wasmtime::Error::msg("plugin does not export alloc"))?
+        .typed::<i32, i32>(&*caller)?;
+    let (ptr, len) = write_guest(&mut *caller, memory, &alloc, bytes)?;
+    Ok(pack(ptr, len))
+}
+
+// 插件可以调用的宿主函数
+fn build_linker(engine: &Engine) -> wasmtime::Result<Linker<HostState>> {
+    let mut linker = Linker::new(engine);
+    preview1::add_to_linker_sync(&mut linker, |state: &mut HostState| &mut state.wasi)?;
+
+    linker.func_wrap("chatshell", "session_cwd", |mut caller: Caller<'_, HostState>| -> wasmtime::Result<i64> {
+        // 调用时从会话读取，不使用事件中缓存的目录
+        let cwd = TERMINAL_MANAGER.lock().unwrap()
+            .get_session(&caller.data().session_id)
+            .and_then(|session| session.current_dir())
+            .unwrap_or_default();
+        return_bytes(&mut caller, cwd.as_bytes())
+    })?;
+
+    linker.func_wrap("chatshell", "notice", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+        let message = read_caller_string(&mut caller, ptr, len)?;
+        let state = caller.data();
+        emit_notice(&state.app_handle, &state.plugin, &state.session_id, message);
+        Ok(())
+    })?;
+
+    linker.func_wrap(
+        "chatshell",
+        "query_history",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32, limit: i32| -> wasmtime::Result<i64> {
+            let query = read_caller_string(&mut caller, ptr, len)?;
+            let limit = match limit {
+                limit if limit <= 0 => DEFAULT_HISTORY_LIMIT,
+                limit => (limit as usize).min(MAX_HISTORY_LIMIT),
+            };
+            let session_id = caller.data().session_id.clone();
+            let query = Some(query).filter(|q| !q.is_empty());
+            let entries: Vec<HistoryEntry> = storage::search_commands(query.as_deref(), Some(session_id.as_str()), limit)
+                .into_iter()
+                .map(|record| HistoryEntry {
+                    command: record.command,
+                    cwd: record.cwd,
+                    exit_code: record.exit_code,
+                    started_at: record.started_at,
+                })
+                .collect();
+            let json = serde_json::to_vec(&entries)?;
+            return_bytes(&mut caller, &json)
+        },
+    )?;
+
+    Ok(linker)
+}
+
+// 一个会话中的插件实例
+struct WasmInstance {
+    store: Store<HostState>,
+    instance: Instance,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+    handle_event: TypedFunc<(i32, i32), i64>,
+}
+
+// 每次调用前重置燃料和超时
+fn reset_budget(store: &mut Store<HostState>) -> wasmtime::Result<()> {
+    store.set_fuel(FUEL_PER_CALL)?;
+    store.set_epoch_deadline(EPOCH_DEADLINE);
+    Ok(())
+}
+
+impl WasmInstance {
+    // 读取打包返回的内容并交还给插件释放
+    fn take_result(&mut self, packed: i64) -> wasmtime::Result<Option<Vec<u8>>> {
+        if packed == 0 {
+            return Ok(None);
+        }
+        let (ptr, len) = unpack(packed);
+        let bytes = read_guest(&self.store, self.memory, ptr, len)?;
+        if let Some(dealloc) = &self.dealloc {
+            dealloc.call(&mut self.store, (ptr, len))?;
+        }
+        Ok(Some(bytes))
+    }
+
+    fn call(&mut self, input: &[u8]) -> wasmtime::Result<Option<Vec<u8>>> {
+        reset_budget(&mut self.store)?;
+        let (ptr, len) = write_guest(&mut self.store, self.memory, &self.alloc, input)?;
+        let packed = self.handle_event.call(&mut self.store, (ptr, len))?;
+        self.take_result(packed)
+    }
+
+    fn manifest(&mut self) -> wasmtime::Result<PluginManifest> {
+        let info = match self.instance.get_typed_func::<(), i64>(&mut self.store, "plugin_info") {
+            Ok(info) => info,
+            Err(_) => return Ok(PluginManifest::default()),
+        };
+        reset_budget(&mut self.store)?;
+        let packed = info.call(&mut self.store, ())?;
+        match self.take_result(packed)? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(PluginManifest::default()),
+        }
+    }
+}
+
+// 编译好的插件，在会话之间共享
+struct WasmModule {
+    name: String,
+    hooks: Option<Vec<String>>,
+    module: Module,
+    linker: Arc<Linker<HostState>>,
+    app_handle: AppHandle,
+}
+
+impl WasmModule {
+    fn instantiate(&self) -> wasmtime::Result<WasmInstance> {
+        let state = HostState {
+            wasi: WasiCtxBuilder::new().inherit_stderr().build_p1(),
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).instances(1).build(),
+            plugin: self.name.clone(),
+            session_id: String::new(),
+            app_handle: self.app_handle.clone(),
+        };
+        let mut store = Store::new(self.module.engine(), state);
+        store.limiter(|state| &mut state.limits);
+        reset_budget(&mut store)?;
+
+        let instance = self.linker.instantiate(&mut store, &self.module)?;
+        // WASI reactor 需要先执行初始化
+        if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut store, "_initialize") {
+            initialize.call(&mut store, ())?;
+        }
+        let memory = instance.get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("plugin does not export memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let dealloc = instance.get_typed_func::<(i32, i32), ()>(&mut store, "dealloc").ok();
+        let handle_event = instance.get_typed_func::<(i32, i32), i64>(&mut store, "handle_event")?;
+        Ok(WasmInstance { store, instance, memory, alloc, dealloc, handle_event })
+    }
+
+    fn create_plugin(&self) -> WasmPlugin {
+        let instance = match self.instantiate() {
+            Ok(instance) => Some(instance),
+            Err(e) => {
+                eprintln!("Failed to instantiate WASM plugin {}: {:#}", self.name, e);
+                None
+            }
+        };
+        WasmPlugin {
+            name: self.name.clone(),
+            hooks: self.hooks.clone(),
+            instance: Arc::new(Mutex::new(instance)),
+            busy: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+pub struct WasmPlugin {
+    name: String,
+    hooks: Option<Vec<String>>,
+    // 在阻塞线程中调用，实例出错后置为 None，不再调用
+    instance: Arc<Mutex<Option<WasmInstance>>>,
+    // 是否有调用仍在阻塞线程中执行
+    busy: Arc<AtomicBool>,
+}
+
+// 调用结束（或任务被丢弃）时清除 busy 标记
+struct BusyGuard(Arc<AtomicBool>);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl WasmPlugin {
+    async fn dispatch(&self, session_id: &str, event: HookEvent<'_>) -> Option<Vec<u8>> {
+        let hook = event.hook();
+        if let Some(hooks) = &self.hooks {
+            if !hooks.iter().any(|h| h == hook) {
+                return None;
+            }
+        }
+        let input = serde_json::to_vec(&HookCall { session_id, event }).ok()?;
+
+        // 上一次调用超时后仍在执行时不再排队新的阻塞任务
+        if self.busy.swap(true, Ordering::AcqRel) {
+            eprintln!("WASM plugin {} is still busy, skipped {}", self.name, hook);
+            return None;
+        }
+        let guard = BusyGuard(self.busy.clone());
+
+        let instance = self.instance.clone();
+        let name = self.name.clone();
+        let session_id = session_id.to_string();
+        tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            let mut slot = instance.lock().unwrap();
+            let current = slot.as_mut()?;
+            current.store.data_mut().session_id = session_id.clone();
+            match current.call(&input) {
+                Ok(output) => output,
+                Err(e) => {
+                    // 燃料耗尽、超时、超出内存限制等异常后实例状态不可信
+                    eprintln!("WASM plugin {} failed in {}, disabled in session {}: {:#}", name, hook, session_id, e);
+                    *slot = None;
+                    None
+                }
+            }
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+}
+
+#[async_trait]
+impl TerminalPlugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn before_command(&mut self, command: &str, session_id: &str) -> CommandAction {
+        let output = match self.dispatch(session_id, HookEvent::BeforeCommand { command }).await {
+            Some(output) => output,
+            None => return CommandAction::Continue,
+        };
+        match serde_json::from_slice::<ActionResponse>(&output) {
+            Ok(response) => response.into(),
+            Err(e) => {
+                eprintln!("Invalid before_command response from WASM plugin {}: {}", self.name, e);
+                CommandAction::Continue
+            }
+        }
+    }
+
+    async fn on_command_start(&mut self, command: &str, session_id: &str) {
+        self.dispatch(session_id, HookEvent::CommandStart { command }).await;
+    }
+
+    async fn on_output(&mut self, output: &str, session_id: &str) {
+        self.dispatch(session_id, HookEvent::Output { output }).await;
+    }
+
+    async fn on_command_end(&mut self, exit_code: Option<i32>, session_id: &str) {
+        self.dispatch(session_id, HookEvent::CommandEnd { exit_code }).await;
+    }
+
+    async fn on_cwd_change(&mut self, cwd: &str, session_id: &str) {
+        self.dispatch(session_id, HookEvent::CwdChange { cwd }).await;
+    }
+
+    async fn on_session_start(&mut self, session_id: &str) {
+        self.dispatch(session_id, HookEvent::SessionStart).await;
+    }
+
+    async fn on_session_end(&mut self, session_id: &str) {
+        self.dispatch(session_id, HookEvent::SessionEnd).await;
+    }
+}
+
+struct WasmHost {
+    engine: Engine,
+    linker: Arc<Linker<HostState>>,
+    app_handle: AppHandle,
+    // 已载入的文件及其注册的插件名
+    loaded: HashMap<PathBuf, String>,
+    // 保持目录监听，drop 后停止热加载
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl WasmHost {
+    fn reload(&mut self, path: &Path) {
+        if !path.exists() {
+            if let Some(name) = self.loaded.remove(path) {
+                unload_plugin(&name);
+                println!("[RUST] Unloaded WASM plugin {}", name);
+            }
+            return;
+        }
+        match self.load(path) {
+            Ok(name) => println!("[RUST] Loaded WASM plugin {} from {}", name, path.display()),
+            Err(e) => eprintln!("Failed to load WASM plugin {}: {}", path.display(), e),
+        }
+    }
+
+    fn load(&mut self, path: &Path) -> AppResult<String> {
+        let module = Module::from_file(&self.engine, path)
+            .map_err(|e| AppError::config_invalid(format!("Failed to compile: {:#}", e)))?;
+        let file_name = path.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+
+        // 先创建一个实例读取插件信息
+        let mut template = WasmModule {
+            name: stem,
+            hooks: None,
+            module,
+            linker: self.linker.clone(),
+            app_handle: self.app_handle.clone(),
+        };
+        let manifest = template.instantiate()
+            .and_then(|mut instance| instance.manifest())
+            .map_err(wasm_error)?;
+        if let Some(name) = manifest.name.as_ref().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            template.name = name.to_string();
+        }
+        template.hooks = manifest.hooks.clone();
+        let name = template.name.clone();
+
+        // 不覆盖内置插件和其他文件载入的同名插件
+        let owned_elsewhere = self.loaded.iter().any(|(p, n)| n == &name && p != path);
+        let owned_here = self.loaded.get(path) == Some(&name);
+        if owned_elsewhere || (!owned_here && PLUGIN_REGISTRY.lock().unwrap().get(&name).is_some()) {
+            return Err(AppError::config_invalid(format!("Plugin name {} is already in use", name)));
+        }
+        // 插件改了名字时先卸载旧的
+        if let Some(old) = self.loaded.insert(path.to_path_buf(), name.clone()) {
+            if old != name {
+                unload_plugin(&old);
+            }
+        }
+
+        let description = manifest.description
+            .filter(|d| !d.is_empty())
+            .unwrap_or_else(|| format!("WASM 插件 {}", file_name));
+        let template = Arc::new(template);
+        let enabled = {
+            let mut registry = PLUGIN_REGISTRY.lock().unwrap();
+            registry.register(&name, &description, manifest.default_enabled, move || Box::new(template.create_plugin()));
+            registry.get(&name).map(|p| registry.is_enabled(p)).unwrap_or(false)
+        };
+        refresh_sessions(&name, enabled);
+        Ok(name)
+    }
+}
+
+// 已打开的会话换成新实例，新启用的插件加载到所有会话
+fn refresh_sessions(name: &str, enabled: bool) {
+    let sessions: Vec<(String, bool)> = TERMINAL_MANAGER.lock().unwrap()
+        .sessions()
+        .map(|s| (s.id.clone(), s.plugins.contains(name)))
+        .collect();
+    for (session_id, loaded) in sessions {
+        if !loaded && !enabled {
+            continue;
+        }
+        let result = set_session_plugin(&session_id, name, false)
+            .and_then(|_| set_session_plugin(&session_id, name, true));
+        if let Err(e) = result {
+            eprintln!("Failed to reload plugin {} in session {}: {}", name, session_id, e);
+        }
+    }
+}
+
+fn unload_plugin(name: &str) {
+    let session_ids: Vec<String> = TERMINAL_MANAGER.lock().unwrap().sessions().map(|s| s.id.clone()).collect();
+    for session_id in session_ids {
+        if let Err(e) = set_session_plugin(&session_id, name, false) {
+            eprintln!("Failed to unload plugin {} from session {}: {}", name, session_id, e);
+        }
+    }
+    PLUGIN_REGISTRY.lock().unwrap().unregister(name);
+}
+
+fn is_wasm(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "wasm")
+}
+
+fn watch(dir: &Path) -> AppResult<RecommendedWatcher> {
+    let (tx, rx) = mpsc::channel::<PathBuf>();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+        Ok(event) => {
+            for path in event.paths.into_iter().filter(|p| is_wasm(p)) {
+                let _ = tx.send(path);
+            }
+        }
+        Err(e) => eprintln!("Plugin directory watch error: {}", e),
+    })
+    .map_err(|e| AppError::io(format!("Failed to watch {}: {}", dir.display(), e)))?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)
+        .map_err(|e| AppError::io(format!("Failed to watch {}: {}", dir.display(), e)))?;
+    std::thread::spawn(move || reload_loop(rx));
+    Ok(watcher)
+}
+
+// 合并短时间内的多次变化后重新载入
+fn reload_loop(rx: mpsc::Receiver<PathBuf>) {
+    while let Ok(first) = rx.recv() {
+        let mut changed = vec![first];
+        while let Ok(path) = rx.recv_timeout(RELOAD_DEBOUNCE) {
+            if !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        let mut host = WASM_HOST.lock().unwrap();
+        if let Some(host) = host.as_mut() {
+            for path in changed {
+                host.reload(&path);
+            }
+        }
+    }
+}
+
+// 应用启动时调用：载入插件目录并开始监听变化
+pub fn init(app_handle: AppHandle, dir: PathBuf) -> AppResult<()> {
+    let dir = dir.join(PLUGIN_DIR);
+    fs::create_dir_all(&dir).map_err(|e| AppError::io(format!("Failed to create {}: {}", dir.display(), e)))?;
+
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config).map_err(wasm_error)?;
+    let ticker = engine.clone();
+    std::thread::Builder::new()
+        .name("wasm-epoch".to_string())
+        .spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            ticker.increment_epoch();
+        })
+        .map_err(|e| AppError::io(format!("Failed to start WASM epoch timer: {}", e)))?;
+    let linker = build_linker(&engine).map_err(wasm_error)?;
+    let mut host = WasmHost {
+        engine,
+        linker: Arc::new(linker),
+        app_handle,
+        loaded: HashMap::new(),
+        _watcher: None,
+    };
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .map_err(|e| AppError::io(format!("Failed to read {}: {}", dir.display(), e)))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_wasm(path))
+        .collect();
+    paths.sort();
+    for path in paths {
+        host.reload(&path);
+    }
+    println!("[RUST] Loaded {} WASM plugins from {}", host.loaded.len(), dir.display());
+    *WASM_HOST.lock().unwrap() = Some(host);
+
+    let watcher = watch(&dir)?;
+    if let Some(host) = WASM_HOST.lock().unwrap().as_mut() {
+        host._watcher = Some(watcher);
+    }
+    Ok(())
+}
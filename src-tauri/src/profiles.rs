@@ -0,0 +1,243 @@
+// src/profiles.rs - 命名的 AI 配置，保存在应用配置目录的 ai_profiles.json 中
+// API key 不写入该文件，而是通过 secrets 模块按配置名保存
+
+use crate::ai::{AIAgent, AIConfig, RedactedAIConfig, AI_AGENT};
+use crate::error::{AppError, AppResult};
+use crate::secrets;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const PROFILES_FILE: &str = "ai_profiles.json";
+
+// 旧版 configure_ai 在没有任何配置时创建的默认名称
+pub const DEFAULT_PROFILE: &str = "default";
+
+pub static PROFILES: Lazy<Mutex<ProfileStore>> = Lazy::new(|| Mutex::new(ProfileStore::default()));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AIProfile {
+    pub name: String,
+    pub config: AIConfig,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileList {
+    #[serde(default)]
+    pub active: Option<String>,
+    #[serde(default)]
+    pub profiles: Vec<AIProfile>,
+}
+
+// 返回给前端的配置列表
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileView {
+    pub name: String,
+    pub config: RedactedAIConfig,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileListView {
+    pub active: Option<String>,
+    pub profiles: Vec<ProfileView>,
+    // 有 key 因系统密钥环不可用而只做了混淆保存
+    pub insecure_key_storage: bool,
+}
+
+#[derive(Default)]
+pub struct ProfileStore {
+    // 未初始化时只保存在内存中
+    path: Option<PathBuf>,
+    data: ProfileList,
+}
+
+impl ProfileStore {
+    pub fn get(&self, name: &str) -> Option<&AIProfile> {
+        self.data.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn active(&self) -> Option<&AIProfile> {
+        self.data.active.as_deref().and_then(|name| self.get(name))
+    }
+
+    pub fn list(&self) -> ProfileListView {
+        ProfileListView {
+            active: self.data.active.clone(),
+            profiles: self.data.profiles.iter().map(|p| ProfileView {
+                name: p.name.clone(),
+                config: RedactedAIConfig::from(&p.config),
+            }).collect(),
+            insecure_key_storage: secrets::file_fallback_used(),
+        }
+    }
+
+    // 新建或更新，第一个配置自动成为当前配置
+    // 前端拿不到原始 key，提交空 key 表示保持不变
+    pub fn upsert(&mut self, name: &str, mut config: AIConfig) -> AppResult<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::config_invalid("Profile name cannot be empty"));
+        }
+
+        if config.api_key.is_empty() {
+            if let Some(existing) = self.get(name) {
+                config.api_key = existing.config.api_key.clone();
+            }
+        } else {
+            secrets::store_key(name, &config.api_key)?;
+        }
+
+        match self.data.profiles.iter_mut().find(|p| p.name == name) {
+            Some(profile) => profile.config = config,
+            None => self.data.profiles.push(AIProfile {
+                name: name.to_string(),
+                config,
+            }),
+        }
+        if self.data.active.is_none() {
+            self.data.active = Some(name.to_string());
+        }
+        self.save()
+    }
+
+    pub fn delete(&mut self, name: &str) -> AppResult<()> {
+        let before = self.data.profiles.len();
+        self.data.profiles.retain(|p| p.name != name);
+        if self.data.profiles.len() == before {
+            return Err(AppError::not_found("Profile", name));
+        }
+        if let Err(e) = secrets::delete_key(name) {
+            eprintln!("Failed to delete API key for {}: {}", name, e);
+        }
+        if self.data.active.as_deref() == Some(name) {
+            self.data.active = self.data.profiles.first().map(|p| p.name.clone());
+        }
+        self.save()
+    }
+
+    pub fn set_active(&mut self, name: &str) -> AppResult<()> {
+        if self.get(name).is_none() {
+            return Err(AppError::not_found("Profile", name));
+        }
+        self.data.active = Some(name.to_string());
+        self.save()
+    }
+
+    // 先写临时文件再改名，避免写入中途退出导致配置损坏
+    fn save(&self) -> AppResult<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let content = serde_json::to_string_pretty(&self.data).map_err(|e| AppError::io(e.to_string()))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, content).map_err(|e| AppError::io(format!("Failed to write {}: {}", tmp.display(), e)))?;
+        fs::rename(&tmp, path).map_err(|e| AppError::io(format!("Failed to save profiles: {}", e)))
+    }
+}
+
+// 应用启动时调用：载入配置文件并用当前配置创建 AI Agent
+pub fn init(dir: PathBuf) -> AppResult<()> {
+    fs::create_dir_all(&dir).map_err(|e| AppError::io(format!("Failed to create {}: {}", dir.display(), e)))?;
+    let path = dir.join(PROFILES_FILE);
+
+    secrets::init(dir.clone());
+
+    let mut data: ProfileList = match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| AppError::config_invalid(format!("Failed to parse {}: {}", path.display(), e)))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => ProfileList::default(),
+        Err(e) => return Err(AppError::io(format!("Failed to read {}: {}", path.display(), e))),
+    };
+
+    // 旧版本以明文保存的 key 迁移到密钥存储，其余从密钥存储读回内存
+    let mut migrated = false;
+    for profile in &mut data.profiles {
+        if !profile.config.api_key.is_empty() {
+            secrets::store_key(&profile.name, &profile.config.api_key)?;
+            migrated = true;
+        } else {
+            match secrets::load_key(&profile.name) {
+                Ok(key) => profile.config.api_key = key.unwrap_or_default(),
+                Err(e) => eprintln!("Failed to load API key for {}: {}", profile.name, e),
+            }
+        }
+    }
+
+    let mut store = PROFILES.lock().unwrap();
+    store.path = Some(path);
+    store.data = data;
+    if migrated {
+        store.save()?;
+    }
+    println!("[RUST] Loaded {} AI profiles", store.data.profiles.len());
+
+    if let Some(profile) = store.active() {
+        // 启动阶段没有其他任务持有锁
+        if let Ok(mut agent) = AI_AGENT.try_lock() {
+            *agent = Some(AIAgent::with_profile(profile.config.clone(), Some(profile.name.clone())));
+        }
+    }
+    Ok(())
+}
+
+pub fn get_profile_config(name: &str) -> AppResult<AIConfig> {
+    PROFILES.lock().unwrap()
+        .get(name)
+        .map(|p| p.config.clone())
+        .ok_or_else(|| AppError::not_found("Profile", name))
+}
+
+// 当前配置变化后重建全局 AI Agent
+async fn refresh_active_agent() {
+    let active = PROFILES.lock().unwrap().active().cloned();
+    let mut agent = AI_AGENT.lock().await;
+    *agent = active.map(|profile| AIAgent::with_profile(profile.config, Some(profile.name)));
+}
+
+// Tauri 命令
+#[tauri::command]
+pub async fn list_ai_profiles() -> AppResult<ProfileListView> {
+    Ok(PROFILES.lock().unwrap().list())
+}
+
+#[tauri::command]
+pub async fn save_ai_profile(name: String, config: AIConfig) -> AppResult<()> {
+    // 访问系统密钥环可能阻塞（例如等待解锁），放到阻塞线程中执行
+    tokio::task::spawn_blocking(move || PROFILES.lock().unwrap().upsert(&name, config))
+        .await
+        ??;
+    refresh_active_agent().await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_ai_profile(name: String) -> AppResult<()> {
+    tokio::task::spawn_blocking(move || PROFILES.lock().unwrap().delete(&name))
+        .await
+        ??;
+    refresh_active_agent().await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn switch_ai_profile(name: String) -> AppResult<()> {
+    PROFILES.lock().unwrap().set_active(&name)?;
+    refresh_active_agent().await;
+    Ok(())
+}
+
+// 兼容旧接口：更新当前配置，没有配置时创建 default
+pub async fn save_active_config(config: AIConfig) -> AppResult<()> {
+    tokio::task::spawn_blocking(move || {
+        let mut store = PROFILES.lock().unwrap();
+        let name = store.data.active.clone().unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+        store.upsert(&name, config)
+    })
+    .await
+    ??;
+    refresh_active_agent().await;
+    Ok(())
+}
@@ -0,0 +1,370 @@
+// src/providers/anthropic.rs - Anthropic Messages API
+
+use super::{for_each_line, merge_tool_call_deltas, send_json, sse_data, ChatProvider};
+use crate::ai::{AIConfig, ChatMessage, ChatStream, FunctionCall, FunctionCallDelta, Tool, ToolCall, ToolCallDelta, Usage};
+use crate::error::{AppError, AppResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+#[derive(Debug, Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolDefinition<'a>>,
+    stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolDefinition<'a> {
+    name: &'a str,
+    description: &'a str,
+    input_schema: &'a serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Message {
+    role: &'static str,
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+    // thinking 等暂不处理的内容块
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    #[serde(default)]
+    r#type: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: StreamMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    MessageDelta {
+        #[serde(default)]
+        usage: Option<AnthropicUsage>,
+    },
+    MessageStop,
+    Error {
+        error: ApiError,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    #[serde(default)]
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+pub struct AnthropicProvider {
+    config: AIConfig,
+    client: reqwest::Client,
+    url: String,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: AIConfig, client: reqwest::Client) -> Self {
+        let base = config.base_url.trim_end_matches('/');
+        let url = if base.ends_with("/v1") {
+            format!("{}/messages", base)
+        } else {
+            format!("{}/v1/messages", base)
+        };
+        Self { config, client, url }
+    }
+
+    fn request<'a>(&'a self, messages: &[ChatMessage], tools: Option<&'a [Tool]>, stream: bool) -> MessagesRequest<'a> {
+        let (system, messages) = convert_messages(messages);
+        MessagesRequest {
+            model: &self.config.model,
+            max_tokens: self.config.max_tokens,
+            temperature: self.config.temperature,
+            system,
+            messages,
+            tools: tools.unwrap_or_default().iter().map(|tool| ToolDefinition {
+                name: &tool.function.name,
+                description: &tool.function.description,
+                input_schema: &tool.function.parameters,
+            }).collect(),
+            stream,
+        }
+    }
+
+    async fn send(&self, request: &MessagesRequest<'_>) -> AppResult<reqwest::Response> {
+        let builder = self.client
+            .post(&self.url)
+            .header("x-api-key", &self.config.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION);
+        let response = send_json(builder, request).await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let response_text = response.text().await.map_err(|e| AppError::ai_network(format!("Failed to get response text: {}", e)))?;
+        match serde_json::from_str::<ErrorResponse>(&response_text) {
+            Ok(error_response) => Err(format_error(&error_response.error)),
+            Err(_) => Err(AppError::AiHttp { status: status.as_u16(), message: response_text }),
+        }
+    }
+}
+
+fn format_error(error: &ApiError) -> AppError {
+    println!("[RUST] API Error: {} ({})", error.message, error.r#type);
+    AppError::AiApi {
+        code: None,
+        r#type: Some(error.r#type.clone()),
+        message: error.message.clone(),
+    }
+}
+
+// system 消息合并为顶层 system 字段；工具结果作为 user 消息中的 tool_result 块；
+// 相邻的同角色消息合并，满足 user/assistant 交替的要求
+fn convert_messages(messages: &[ChatMessage]) -> (Option<String>, Vec<Message>) {
+    let mut system: Vec<&str> = Vec::new();
+    let mut converted: Vec<Message> = Vec::new();
+
+    for message in messages {
+        let (role, blocks) = match message.role.as_str() {
+            "system" => {
+                if let Some(content) = &message.content {
+                    system.push(content);
+                }
+                continue;
+            }
+            "assistant" => {
+                let mut blocks = Vec::new();
+                if let Some(text) = message.content.as_ref().filter(|t| !t.is_empty()) {
+                    blocks.push(ContentBlock::Text { text: text.clone() });
+                }
+                for call in message.tool_calls.iter().flatten() {
+                    blocks.push(ContentBlock::ToolUse {
+                        id: call.id.clone(),
+                        name: call.function.name.clone(),
+                        input: serde_json::from_str(&call.function.arguments)
+                            .unwrap_or_else(|_| serde_json::json!({})),
+                    });
+                }
+                ("assistant", blocks)
+            }
+            "tool" => ("user", vec![ContentBlock::ToolResult {
+                tool_use_id: message.tool_call_id.clone().unwrap_or_default(),
+                content: message.content.clone().unwrap_or_default(),
+            }]),
+            _ => {
+                let text = message.content.clone().unwrap_or_default();
+                ("user", if text.is_empty() { Vec::new() } else { vec![ContentBlock::Text { text }] })
+            }
+        };
+
+        if blocks.is_empty() {
+            continue;
+        }
+        match converted.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => converted.push(Message { role, content: blocks }),
+        }
+    }
+
+    let system = if system.is_empty() { None } else { Some(system.join("\n\n")) };
+    (system, converted)
+}
+
+fn convert_usage(usage: &AnthropicUsage) -> Usage {
+    Usage {
+        prompt_tokens: usage.input_tokens,
+        completion_tokens: usage.output_tokens,
+        total_tokens: usage.input_tokens + usage.output_tokens,
+    }
+}
+
+#[async_trait]
+impl ChatProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    async fn complete(&self, messages: &[ChatMessage], tools: Option<&[Tool]>) -> AppResult<ChatMessage> {
+        let request = self.request(messages, tools, false);
+        println!("[RUST] Making request to: {}", self.url);
+
+        let response = self.send(&request).await?;
+        let response_text = response.text().await.map_err(|e| AppError::ai_network(format!("Failed to get response text: {}", e)))?;
+        println!("[RUST] Raw response: {}", response_text);
+
+        let response: MessagesResponse = serde_json::from_str(&response_text)
+            .map_err(|e| AppError::from(format!("Failed to parse response: {}", e)))?;
+        if let Some(usage) = &response.usage {
+            println!("[RUST] Usage: {:?}", convert_usage(usage));
+        }
+
+        let mut content = String::new();
+        let mut tool_calls = Vec::new();
+        for block in response.content {
+            match block {
+                ContentBlock::Text { text } => content.push_str(&text),
+                ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                    id,
+                    r#type: "function".to_string(),
+                    function: FunctionCall {
+                        name,
+                        arguments: input.to_string(),
+                    },
+                }),
+                _ => {}
+            }
+        }
+        Ok(ChatMessage::assistant(content, tool_calls))
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], tools: Option<&[Tool]>, stream: &ChatStream) -> AppResult<ChatMessage> {
+        let request = self.request(messages, tools, true);
+        println!("[RUST] Making streaming request to: {}", self.url);
+        let response = self.send(&request).await?;
+
+        let mut content = String::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        // 内容块序号 -> 工具调用序号
+        let mut tool_indexes: HashMap<usize, usize> = HashMap::new();
+        let mut usage = AnthropicUsage::default();
+
+        for_each_line(response, |line| {
+            let data = match sse_data(line) {
+                Some(data) => data,
+                None => return Ok(true),
+            };
+            let event: StreamEvent = match serde_json::from_str(data) {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Failed to parse stream event: {} ({})", e, data);
+                    return Ok(true);
+                }
+            };
+
+            match event {
+                StreamEvent::MessageStart { message } => {
+                    if let Some(start) = message.usage {
+                        usage.input_tokens = start.input_tokens;
+                    }
+                }
+                StreamEvent::ContentBlockStart { index, content_block: ContentBlock::ToolUse { id, name, .. } } => {
+                    let tool_index = tool_indexes.len();
+                    tool_indexes.insert(index, tool_index);
+                    let deltas = vec![ToolCallDelta {
+                        index: tool_index,
+                        id: Some(id),
+                        function: Some(FunctionCallDelta { name: Some(name), arguments: None }),
+                    }];
+                    merge_tool_call_deltas(&mut tool_calls, &deltas);
+                    stream.emit_delta(None, Some(deltas));
+                }
+                StreamEvent::ContentBlockDelta { delta: BlockDelta::TextDelta { text }, .. } => {
+                    content.push_str(&text);
+                    stream.emit_delta(Some(text), None);
+                }
+                StreamEvent::ContentBlockDelta { index, delta: BlockDelta::InputJsonDelta { partial_json } } => {
+                    if let Some(&tool_index) = tool_indexes.get(&index) {
+                        let deltas = vec![ToolCallDelta {
+                            index: tool_index,
+                            id: None,
+                            function: Some(FunctionCallDelta { name: None, arguments: Some(partial_json) }),
+                        }];
+                        merge_tool_call_deltas(&mut tool_calls, &deltas);
+                        stream.emit_delta(None, Some(deltas));
+                    }
+                }
+                StreamEvent::MessageDelta { usage: Some(delta) } => {
+                    usage.output_tokens = delta.output_tokens;
+                }
+                StreamEvent::MessageStop => return Ok(false),
+                StreamEvent::Error { error } => return Err(format_error(&error)),
+                _ => {}
+            }
+            Ok(true)
+        })
+        .await?;
+
+        stream.add_usage(&convert_usage(&usage));
+
+        // 没有参数的工具调用不会收到 input_json_delta
+        for call in &mut tool_calls {
+            if call.function.arguments.is_empty() {
+                call.function.arguments = "{}".to_string();
+            }
+        }
+        Ok(ChatMessage::assistant(content, tool_calls))
+    }
+}
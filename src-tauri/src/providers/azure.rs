@@ -0,0 +1,53 @@
+// src/providers/azure.rs - Azure OpenAI
+//
+// 请求体与 OpenAI 相同，区别在于按部署名拼接地址、通过 api-version 查询参数
+// 选择接口版本，并使用 api-key 请求头鉴权
+
+use super::openai::OpenAIProvider;
+use super::ChatProvider;
+use crate::ai::{AIConfig, ChatMessage, ChatStream, Tool};
+use crate::error::AppResult;
+use async_trait::async_trait;
+
+pub const DEFAULT_API_VERSION: &str = "2024-10-21";
+
+pub struct AzureOpenAIProvider {
+    inner: OpenAIProvider,
+}
+
+impl AzureOpenAIProvider {
+    pub fn new(config: AIConfig, client: reqwest::Client) -> Self {
+        // 未单独配置部署名时使用 model
+        let deployment = config.deployment.clone()
+            .filter(|d| !d.is_empty())
+            .unwrap_or_else(|| config.model.clone());
+        let api_version = config.api_version.clone()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| DEFAULT_API_VERSION.to_string());
+        let url = format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            config.base_url.trim_end_matches('/'),
+            deployment,
+            api_version
+        );
+        let auth = Some(("api-key", config.api_key.clone()));
+        Self {
+            inner: OpenAIProvider::with_endpoint(config, client, url, auth, false),
+        }
+    }
+}
+
+#[async_trait]
+impl ChatProvider for AzureOpenAIProvider {
+    fn name(&self) -> &'static str {
+        "azure"
+    }
+
+    async fn complete(&self, messages: &[ChatMessage], tools: Option<&[Tool]>) -> AppResult<ChatMessage> {
+        self.inner.complete(messages, tools).await
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], tools: Option<&[Tool]>, stream: &ChatStream) -> AppResult<ChatMessage> {
+        self.inner.stream(messages, tools, stream).await
+    }
+}
@@ -0,0 +1,150 @@
+// src/providers/mod.rs - 不同模型服务的统一接口
+
+pub mod anthropic;
+pub mod azure;
+pub mod ollama;
+pub mod openai;
+
+use crate::ai::{AIConfig, ChatMessage, ChatStream, FunctionCall, Tool, ToolCall, ToolCallDelta};
+use crate::error::{AppError, AppResult};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+// AIConfig 中选择的服务类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    // OpenAI 及兼容接口（DeepSeek、llama.cpp server、vLLM 等）
+    #[default]
+    OpenAI,
+    Anthropic,
+    Ollama,
+    Azure,
+}
+
+// 每个服务负责把通用的消息和工具定义转换成自己的请求格式，
+// 并把响应（包括流式增量和工具调用）转换回 ChatMessage
+#[async_trait]
+pub trait ChatProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // 本地服务通常不需要 API key
+    fn requires_api_key(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, messages: &[ChatMessage], tools: Option<&[Tool]>) -> AppResult<ChatMessage>;
+
+    // 流式请求：通过 stream 推送增量和用量，返回拼装好的完整消息
+    async fn stream(&self, messages: &[ChatMessage], tools: Option<&[Tool]>, stream: &ChatStream) -> AppResult<ChatMessage>;
+}
+
+pub fn create_provider(config: &AIConfig) -> Arc<dyn ChatProvider> {
+    let client = reqwest::Client::new();
+    match config.provider {
+        ProviderKind::OpenAI => Arc::new(openai::OpenAIProvider::new(config.clone(), client)),
+        ProviderKind::Anthropic => Arc::new(anthropic::AnthropicProvider::new(config.clone(), client)),
+        ProviderKind::Ollama => Arc::new(ollama::OllamaProvider::new(config.clone(), client)),
+        ProviderKind::Azure => Arc::new(azure::AzureOpenAIProvider::new(config.clone(), client)),
+    }
+}
+
+// 逐行读取响应体（SSE 或 NDJSON），回调返回 false 时提前结束
+pub(crate) async fn for_each_line<F>(response: reqwest::Response, mut handle: F) -> AppResult<()>
+where
+    F: FnMut(&str) -> AppResult<bool>,
+{
+    // 按字节缓冲，只解码完整的行，避免多字节字符被网络分块截断
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut body = response.bytes_stream();
+
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| AppError::ai_network(format!("Stream read failed: {}", e)))?;
+        buffer.extend_from_slice(&chunk);
+
+        // 最后一行可能不完整，留到下一块
+        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&raw[..pos]);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if !handle(line)? {
+                return Ok(());
+            }
+        }
+    }
+
+    let line = String::from_utf8_lossy(&buffer);
+    let line = line.trim();
+    if !line.is_empty() {
+        handle(line)?;
+    }
+    Ok(())
+}
+
+// 按 index 把流式的工具调用片段拼接成完整调用
+pub(crate) fn merge_tool_call_deltas(tool_calls: &mut Vec<ToolCall>, deltas: &[ToolCallDelta]) {
+    for delta in deltas {
+        while tool_calls.len() <= delta.index {
+            tool_calls.push(ToolCall {
+                id: String::new(),
+                r#type: "function".to_string(),
+                function: FunctionCall {
+                    name: String::new(),
+                    arguments: String::new(),
+                },
+            });
+        }
+        let call = &mut tool_calls[delta.index];
+        if let Some(id) = &delta.id {
+            call.id.push_str(id);
+        }
+        if let Some(function) = &delta.function {
+            if let Some(name) = &function.name {
+                call.function.name.push_str(name);
+            }
+            if let Some(arguments) = &function.arguments {
+                call.function.arguments.push_str(arguments);
+            }
+        }
+    }
+}
+
+
+// SSE 的 data 行内容
+pub(crate) fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(|data| data.trim())
+}
+
+pub(crate) async fn send_json<T: Serialize>(request: reqwest::RequestBuilder, body: &T) -> AppResult<reqwest::Response> {
+    let response = request
+        .header("Content-Type", "application/json")
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| AppError::ai_network(format!("Request failed: {}", e)))?;
+    println!("[RUST] Response status: {}", response.status());
+    Ok(response)
+}
+
+// 判断地址是否指向本机，本地服务允许不配置 API key。
+// 主机名按 IP 地址解析，避免 127.example.com 这类域名被当成本机
+pub(crate) fn is_local_url(url: &str) -> bool {
+    // 没有 scheme 的 localhost:8080 会被解析成 scheme 为 localhost 的地址
+    let parsed = reqwest::Url::parse(url).ok()
+        .filter(|url| url.has_host())
+        .or_else(|| reqwest::Url::parse(&format!("http://{}", url)).ok());
+    let host = match parsed.as_ref().and_then(|url| url.host_str()) {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => return false,
+    };
+    if host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback() || ip.is_unspecified())
+}
@@ -0,0 +1,261 @@
+// src/providers/ollama.rs - Ollama 原生 /api/chat 接口
+//
+// 流式响应为逐行 JSON（NDJSON）；工具调用没有 id，参数是 JSON 对象而不是字符串
+
+use super::{for_each_line, send_json, ChatProvider};
+use crate::ai::{AIConfig, ChatMessage, ChatStream, FunctionCall, FunctionCallDelta, Tool, ToolCall, ToolCallDelta, Usage};
+use crate::error::{AppError, AppResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<OllamaMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<&'a [Tool]>,
+    stream: bool,
+    options: Options,
+}
+
+#[derive(Debug, Serialize)]
+struct Options {
+    temperature: f32,
+    num_predict: u32,
+    num_ctx: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OllamaFunction {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: u32,
+    #[serde(default)]
+    eval_count: u32,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+pub struct OllamaProvider {
+    config: AIConfig,
+    client: reqwest::Client,
+    url: String,
+}
+
+impl OllamaProvider {
+    pub fn new(config: AIConfig, client: reqwest::Client) -> Self {
+        let url = format!("{}/api/chat", config.base_url.trim_end_matches('/'));
+        Self { config, client, url }
+    }
+
+    fn request<'a>(&'a self, messages: &[ChatMessage], tools: Option<&'a [Tool]>, stream: bool) -> ChatRequest<'a> {
+        ChatRequest {
+            model: &self.config.model,
+            messages: convert_messages(messages),
+            tools,
+            stream,
+            options: Options {
+                temperature: self.config.temperature,
+                num_predict: self.config.max_tokens,
+                num_ctx: self.config.context_window,
+            },
+        }
+    }
+
+    async fn send(&self, request: &ChatRequest<'_>) -> AppResult<reqwest::Response> {
+        let mut builder = self.client.post(&self.url);
+        // 通过反向代理访问时可能需要鉴权
+        if !self.config.api_key.is_empty() {
+            builder = builder.header("Authorization", format!("Bearer {}", self.config.api_key));
+        }
+        let response = send_json(builder, request).await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let response_text = response.text().await.map_err(|e| AppError::ai_network(format!("Failed to get response text: {}", e)))?;
+        match serde_json::from_str::<ErrorResponse>(&response_text) {
+            Ok(error_response) => Err(api_error(error_response.error)),
+            Err(_) => Err(AppError::AiHttp { status: status.as_u16(), message: response_text }),
+        }
+    }
+}
+
+// Ollama 的错误只有一条消息，没有类型和错误码
+fn api_error(message: String) -> AppError {
+    AppError::AiApi { code: None, r#type: None, message }
+}
+
+// 工具结果通过 tool_name 关联，需要从之前的 assistant 消息中找到调用名
+fn convert_messages(messages: &[ChatMessage]) -> Vec<OllamaMessage> {
+    let mut tool_names: HashMap<&str, &str> = HashMap::new();
+    let mut converted = Vec::with_capacity(messages.len());
+
+    for message in messages {
+        let mut tool_calls = Vec::new();
+        for call in message.tool_calls.iter().flatten() {
+            tool_names.insert(&call.id, &call.function.name);
+            tool_calls.push(OllamaToolCall {
+                function: OllamaFunction {
+                    name: call.function.name.clone(),
+                    arguments: serde_json::from_str(&call.function.arguments)
+                        .unwrap_or_else(|_| serde_json::json!({})),
+                },
+            });
+        }
+
+        let tool_name = message.tool_call_id.as_deref()
+            .and_then(|id| tool_names.get(id))
+            .map(|name| name.to_string());
+
+        converted.push(OllamaMessage {
+            role: message.role.clone(),
+            content: message.content.clone().unwrap_or_default(),
+            tool_calls,
+            tool_name,
+        });
+    }
+    converted
+}
+
+fn convert_tool_call(call: OllamaToolCall, index: usize) -> ToolCall {
+    ToolCall {
+        id: format!("call_{}", index),
+        r#type: "function".to_string(),
+        function: FunctionCall {
+            name: call.function.name,
+            arguments: call.function.arguments.to_string(),
+        },
+    }
+}
+
+fn convert_usage(response: &ChatResponse) -> Usage {
+    Usage {
+        prompt_tokens: response.prompt_eval_count,
+        completion_tokens: response.eval_count,
+        total_tokens: response.prompt_eval_count + response.eval_count,
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    async fn complete(&self, messages: &[ChatMessage], tools: Option<&[Tool]>) -> AppResult<ChatMessage> {
+        let request = self.request(messages, tools, false);
+        println!("[RUST] Making request to: {}", self.url);
+
+        let response = self.send(&request).await?;
+        let response_text = response.text().await.map_err(|e| AppError::ai_network(format!("Failed to get response text: {}", e)))?;
+        println!("[RUST] Raw response: {}", response_text);
+
+        let response: ChatResponse = serde_json::from_str(&response_text)
+            .map_err(|e| AppError::from(format!("Failed to parse response: {}", e)))?;
+        if let Some(error) = response.error {
+            return Err(api_error(error));
+        }
+
+        let message = response.message.ok_or_else(|| AppError::from("No response from AI"))?;
+        let tool_calls = message.tool_calls.into_iter()
+            .enumerate()
+            .map(|(index, call)| convert_tool_call(call, index))
+            .collect();
+        Ok(ChatMessage::assistant(message.content, tool_calls))
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], tools: Option<&[Tool]>, stream: &ChatStream) -> AppResult<ChatMessage> {
+        let request = self.request(messages, tools, true);
+        println!("[RUST] Making streaming request to: {}", self.url);
+        let response = self.send(&request).await?;
+
+        let mut content = String::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+
+        for_each_line(response, |line| {
+            let chunk: ChatResponse = match serde_json::from_str(line) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    eprintln!("Failed to parse stream chunk: {} ({})", e, line);
+                    return Ok(true);
+                }
+            };
+            if let Some(error) = chunk.error {
+                return Err(api_error(error));
+            }
+
+            if let Some(message) = &chunk.message {
+                if !message.content.is_empty() {
+                    content.push_str(&message.content);
+                    stream.emit_delta(Some(message.content.clone()), None);
+                }
+
+                // Ollama 一次性返回完整的工具调用
+                let mut deltas = Vec::new();
+                for call in message.tool_calls.iter().cloned() {
+                    let call = convert_tool_call(call, tool_calls.len());
+                    deltas.push(ToolCallDelta {
+                        index: tool_calls.len(),
+                        id: Some(call.id.clone()),
+                        function: Some(FunctionCallDelta {
+                            name: Some(call.function.name.clone()),
+                            arguments: Some(call.function.arguments.clone()),
+                        }),
+                    });
+                    tool_calls.push(call);
+                }
+                if !deltas.is_empty() {
+                    stream.emit_delta(None, Some(deltas));
+                }
+            }
+
+            if chunk.done {
+                stream.add_usage(&convert_usage(&chunk));
+                return Ok(false);
+            }
+            Ok(true)
+        })
+        .await?;
+
+        Ok(ChatMessage::assistant(content, tool_calls))
+    }
+}
@@ -0,0 +1,240 @@
+// src/providers/openai.rs - OpenAI 兼容的 /chat/completions 接口
+
+use super::{for_each_line, is_local_url, merge_tool_call_deltas, send_json, sse_data, ChatProvider};
+use crate::ai::{AIConfig, ChatMessage, ChatStream, Tool, ToolCall, ToolCallDelta, Usage};
+use crate::error::{AppError, AppResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatRequest<'a> {
+    // Azure 按部署名路由，不需要 model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<&'a str>,
+    pub messages: &'a [ChatMessage],
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<&'a [Tool]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub choices: Vec<Choice>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Choice {
+    pub message: ChatMessage,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+// 流式响应中的一个数据块
+#[derive(Debug, Clone, Deserialize)]
+pub struct StreamChunk {
+    #[serde(default)]
+    pub choices: Vec<StreamChoice>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    // 流式响应开始后出错时（如限流、上游超时）服务端发送的错误
+    #[serde(default)]
+    pub error: Option<ErrorDetail>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StreamChoice {
+    #[serde(default)]
+    pub delta: StreamDelta,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StreamDelta {
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    pub message: String,
+    #[serde(default)]
+    pub r#type: Option<String>,
+    #[serde(default)]
+    pub param: Option<String>,
+    #[serde(default)]
+    pub code: Option<serde_json::Value>,
+}
+
+pub struct OpenAIProvider {
+    config: AIConfig,
+    client: reqwest::Client,
+    url: String,
+    // 鉴权请求头，如 ("Authorization", "Bearer ...") 或 Azure 的 ("api-key", ...)
+    auth: Option<(&'static str, String)>,
+    send_model: bool,
+}
+
+impl OpenAIProvider {
+    pub fn new(config: AIConfig, client: reqwest::Client) -> Self {
+        let url = format!("{}/chat/completions", config.base_url.trim_end_matches('/'));
+        let auth = if config.api_key.is_empty() {
+            None
+        } else {
+            Some(("Authorization", format!("Bearer {}", config.api_key)))
+        };
+        Self::with_endpoint(config, client, url, auth, true)
+    }
+
+    pub fn with_endpoint(
+        config: AIConfig,
+        client: reqwest::Client,
+        url: String,
+        auth: Option<(&'static str, String)>,
+        send_model: bool,
+    ) -> Self {
+        Self {
+            config,
+            client,
+            url,
+            auth,
+            send_model,
+        }
+    }
+
+    fn request<'a>(&'a self, messages: &'a [ChatMessage], tools: Option<&'a [Tool]>, stream: bool) -> ChatRequest<'a> {
+        ChatRequest {
+            model: if self.send_model { Some(&self.config.model) } else { None },
+            messages,
+            max_tokens: self.config.max_tokens,
+            temperature: self.config.temperature,
+            stream,
+            tools,
+            stream_options: if stream { Some(StreamOptions { include_usage: true }) } else { None },
+        }
+    }
+
+    async fn send(&self, request: &ChatRequest<'_>) -> AppResult<reqwest::Response> {
+        let mut builder = self.client.post(&self.url);
+        if let Some((name, value)) = &self.auth {
+            builder = builder.header(*name, value);
+        }
+        let response = send_json(builder, request).await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        // 尝试解析错误响应，无法解析时返回原始响应
+        let response_text = response.text().await.map_err(|e| AppError::ai_network(format!("Failed to get response text: {}", e)))?;
+        match serde_json::from_str::<ErrorResponse>(&response_text) {
+            Ok(error_response) => Err(api_error(error_response.error)),
+            Err(_) => Err(AppError::AiHttp { status: status.as_u16(), message: response_text }),
+        }
+    }
+}
+
+fn api_error(error: ErrorDetail) -> AppError {
+    println!("[RUST] API Error: {}", error.message);
+    // code 可能是字符串也可能是数字
+    let code = error.code.map(|code| match code {
+        serde_json::Value::String(code) => code,
+        other => other.to_string(),
+    });
+    AppError::AiApi { code, r#type: error.r#type, message: error.message }
+}
+
+#[async_trait]
+impl ChatProvider for OpenAIProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    // llama.cpp server 等本地兼容服务不需要 API key
+    fn requires_api_key(&self) -> bool {
+        !is_local_url(&self.config.base_url)
+    }
+
+    async fn complete(&self, messages: &[ChatMessage], tools: Option<&[Tool]>) -> AppResult<ChatMessage> {
+        let request = self.request(messages, tools, false);
+        println!("[RUST] Making request to: {}", self.url);
+        println!("[RUST] Request body: {}", serde_json::to_string_pretty(&request).unwrap());
+
+        let response = self.send(&request).await?;
+        let response_text = response.text().await.map_err(|e| AppError::ai_network(format!("Failed to get response text: {}", e)))?;
+        println!("[RUST] Raw response: {}", response_text);
+
+        let chat_response: ChatResponse = serde_json::from_str(&response_text)
+            .map_err(|e| AppError::from(format!("Failed to parse response: {}", e)))?;
+
+        chat_response.choices.into_iter().next()
+            .map(|choice| choice.message)
+            .ok_or_else(|| AppError::from("No response from AI"))
+    }
+
+    async fn stream(&self, messages: &[ChatMessage], tools: Option<&[Tool]>, stream: &ChatStream) -> AppResult<ChatMessage> {
+        let request = self.request(messages, tools, true);
+        println!("[RUST] Making streaming request to: {}", self.url);
+        let response = self.send(&request).await?;
+
+        let mut content = String::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+
+        for_each_line(response, |line| {
+            let data = match sse_data(line) {
+                Some(data) => data,
+                None => return Ok(true),
+            };
+            if data == "[DONE]" {
+                return Ok(false);
+            }
+
+            let chunk: StreamChunk = match serde_json::from_str(data) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    eprintln!("Failed to parse stream chunk: {} ({})", e, data);
+                    return Ok(true);
+                }
+            };
+            if let Some(error) = chunk.error {
+                return Err(api_error(error));
+            }
+
+            if let Some(usage) = &chunk.usage {
+                stream.add_usage(usage);
+            }
+
+            for choice in chunk.choices {
+                let delta = choice.delta;
+                if let Some(text) = &delta.content {
+                    content.push_str(text);
+                }
+                if let Some(deltas) = &delta.tool_calls {
+                    merge_tool_call_deltas(&mut tool_calls, deltas);
+                }
+                if delta.content.is_some() || delta.tool_calls.is_some() {
+                    stream.emit_delta(delta.content, delta.tool_calls);
+                }
+            }
+            Ok(true)
+        })
+        .await?;
+
+        Ok(ChatMessage::assistant(content, tool_calls))
+    }
+}
@@ -0,0 +1,589 @@
+// src/sandbox.rs - 在一次性的受限子进程中执行 AI 提出的命令
+//
+// 命令不进入用户的交互终端，而是交给独立的 /bin/sh 子进程，环境变量只保留
+// ENV_ALLOWLIST 中的几项。子进程在 exec 前依次：
+//   1. 建立新的会话，超时或结束后整组清理
+//   2. 设置资源限制（内存、CPU 时间、进程数、文件大小）
+//   3. 尝试进入新的用户/挂载/PID（以及网络）命名空间，把工作目录只读绑定挂载到自身
+//   4. 用 landlock 只允许读取工作目录和系统目录，只有临时目录可写；
+//      内核支持时同时禁止向沙箱外发送信号和连接抽象 unix 套接字
+//   5. 安装 seccomp 过滤器，禁止挂载、ptrace、加载内核模块、创建 unix 套接字等，
+//      关闭网络时禁止创建 IP 套接字
+//   6. 进入 PID 命名空间时再 fork 一次，命令作为新命名空间的 1 号进程执行
+// 资源限制和 seccomp 总是生效。内核支持 landlock 时要求规则完全生效，否则至少要求
+// 工作目录只读绑定成功；PID 命名空间和 landlock 信号隔离都不可用时同样拒绝执行。
+// 审批时是否信任沙箱由 ApprovalPolicy::auto_approve_sandboxed 决定，见 filesystem_confined。
+
+use std::path::Path;
+
+// 默认限制
+const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_MEMORY_MB: u64 = 1024;
+const DEFAULT_CPU_SECONDS: u64 = 30;
+const DEFAULT_MAX_PROCESSES: u64 = 64;
+const DEFAULT_MAX_FILE_MB: u64 = 64;
+
+// 返回给模型的输出上限
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+// 命令结束后等待后台进程释放输出管道的时间
+#[cfg(target_os = "linux")]
+const OUTPUT_DRAIN_MS: u64 = 500;
+
+#[derive(Debug, Clone)]
+pub struct SandboxOptions {
+    pub allow_network: bool,
+    pub timeout_ms: u64,
+    pub memory_mb: u64,
+    pub cpu_seconds: u64,
+    // 在当前用户已有进程数之外允许新建的进程数
+    pub max_processes: u64,
+    pub max_file_mb: u64,
+}
+
+impl Default for SandboxOptions {
+    fn default() -> Self {
+        Self {
+            allow_network: false,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            memory_mb: DEFAULT_MEMORY_MB,
+            cpu_seconds: DEFAULT_CPU_SECONDS,
+            max_processes: DEFAULT_MAX_PROCESSES,
+            max_file_mb: DEFAULT_MAX_FILE_MB,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SandboxResult {
+    pub output: String,
+    pub exit_code: Option<i32>,
+    // 被信号结束时的信号编号，例如超出 CPU 限制时的 SIGXCPU
+    pub signal: Option<i32>,
+    pub timed_out: bool,
+    pub truncated: bool,
+    pub duration_ms: u64,
+}
+
+// 沙箱能否把整个文件系统限制为只读
+#[cfg(not(target_os = "linux"))]
+pub fn filesystem_confined() -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+pub fn filesystem_confined() -> bool {
+    let temp_dir = std::env::temp_dir();
+    linux::landlock_ruleset(&temp_dir, &temp_dir).is_ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn run(_command: &str, _cwd: &Path, _options: &SandboxOptions) -> Result<SandboxResult, String> {
+    Err("Sandboxed execution is only supported on Linux".to_string())
+}
+
+// 在临时目录中执行命令，结束后删除临时目录
+#[cfg(target_os = "linux")]
+pub async fn run(command: &str, cwd: &Path, options: &SandboxOptions) -> Result<SandboxResult, String> {
+    // 工作目录整体可读，不能是根目录或包含主目录的目录
+    let home = std::env::var_os("HOME").map(std::path::PathBuf::from);
+    if cwd == Path::new("/") || home.is_some_and(|home| home.starts_with(cwd)) {
+        return Err(format!(
+            "Sandboxed commands cannot run in {}, it would expose the home directory; cd into a project directory first",
+            cwd.display()
+        ));
+    }
+
+    let scratch = std::env::temp_dir().join(format!("chatshell-sandbox-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&scratch)
+        .map_err(|e| format!("Failed to create sandbox directory: {}", e))?;
+
+    let result = linux::run_confined(command, cwd, &scratch, options).await;
+
+    if let Err(e) = std::fs::remove_dir_all(&scratch) {
+        eprintln!("Failed to remove sandbox directory {}: {}", scratch.display(), e);
+    }
+    result
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{SandboxOptions, SandboxResult, MAX_OUTPUT_BYTES, OUTPUT_DRAIN_MS};
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, CompatLevel, Compatible, Ruleset, RulesetAttr, RulesetCreated, RulesetCreatedAttr,
+        RulesetStatus, Scope, ABI,
+    };
+    use seccompiler::{BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter, SeccompRule, TargetArch};
+    use std::collections::BTreeMap;
+    use std::ffi::{CStr, CString};
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::os::fd::{FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::Path;
+    use std::process::Stdio;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    #[cfg(target_env = "gnu")]
+    type Resource = libc::__rlimit_resource_t;
+    #[cfg(not(target_env = "gnu"))]
+    type Resource = libc::c_int;
+
+    // 沙箱中一律禁止的系统调用
+    const DENIED_SYSCALLS: &[i64] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_userfaultfd,
+        libc::SYS_acct,
+        libc::SYS_settimeofday,
+        libc::SYS_clock_settime,
+        // io_uring 可以绕过针对 socket 的过滤
+        libc::SYS_io_uring_setup,
+    ];
+
+    // 关闭网络时禁止的套接字类型，netlink 不受影响
+    const NETWORK_FAMILIES: &[libc::c_int] = &[libc::AF_INET, libc::AF_INET6, libc::AF_PACKET];
+
+    // 一律禁止 unix 套接字，避免通过 D-Bus、ssh-agent、docker 等宿主服务执行命令
+    const LOCAL_FAMILIES: &[libc::c_int] = &[libc::AF_UNIX];
+
+    // 传给沙箱的环境变量，其余（令牌、SSH_AUTH_SOCK、DBUS_SESSION_BUS_ADDRESS 等）一律不传
+    const ENV_ALLOWLIST: &[&str] = &[
+        "PATH", "HOME", "USER", "LOGNAME", "SHELL", "TERM", "TZ", "LANG", "LANGUAGE", "LC_ALL", "LC_CTYPE", "LC_MESSAGES",
+    ];
+
+    // 工作目录以外允许读取的系统目录，不存在的会被跳过
+    const SYSTEM_PATHS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/dev/urandom", "/dev/random", "/dev/zero"];
+
+    // 子进程 exec 前需要的全部数据，都在父进程中准备好，
+    // 避免 fork 之后在子进程中分配内存
+    struct Confinement {
+        memory_bytes: u64,
+        cpu_seconds: u64,
+        max_processes: u64,
+        max_file_bytes: u64,
+        namespaces: libc::c_int,
+        uid_map: Vec<u8>,
+        gid_map: Vec<u8>,
+        cwd: CString,
+        // 在 exec 前取出并生效，pre_exec 的闭包只会调用一次
+        landlock: Option<RulesetCreated>,
+        // landlock 是否同时限制了向沙箱外发送信号
+        signals_scoped: bool,
+        seccomp: BpfProgram,
+    }
+
+    impl Confinement {
+        fn prepare(cwd: &Path, scratch: &Path, options: &SandboxOptions) -> Result<Self, String> {
+            let mut namespaces = libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWPID;
+            if !options.allow_network {
+                namespaces |= libc::CLONE_NEWNET;
+            }
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            let (landlock, signals_scoped) = match landlock_ruleset(cwd, scratch) {
+                Ok((ruleset, scoped)) => (Some(ruleset), scoped),
+                Err(e) => {
+                    eprintln!("Landlock unavailable, sandbox relies on a read-only bind mount: {}", e);
+                    (None, false)
+                }
+            };
+
+            Ok(Self {
+                memory_bytes: options.memory_mb * 1024 * 1024,
+                cpu_seconds: options.cpu_seconds,
+                max_processes: count_user_processes(uid) + options.max_processes,
+                max_file_bytes: options.max_file_mb * 1024 * 1024,
+                namespaces,
+                uid_map: format!("{} {} 1", uid, uid).into_bytes(),
+                gid_map: format!("{} {} 1", gid, gid).into_bytes(),
+                cwd: CString::new(cwd.as_os_str().as_bytes())
+                    .map_err(|_| "Invalid working directory".to_string())?,
+                landlock,
+                signals_scoped,
+                seccomp: seccomp_filter(options.allow_network)?,
+            })
+        }
+
+        // 在子进程中 exec 之前调用，fork 之后不能分配内存。
+        // 文件系统或进程隔离达不到要求时返回 ENOTSUP
+        fn apply(&mut self) -> io::Result<()> {
+            if unsafe { libc::setsid() } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            set_limit(libc::RLIMIT_AS, self.memory_bytes)?;
+            set_limit(libc::RLIMIT_CPU, self.cpu_seconds)?;
+            set_limit(libc::RLIMIT_NPROC, self.max_processes)?;
+            set_limit(libc::RLIMIT_FSIZE, self.max_file_bytes)?;
+            set_limit(libc::RLIMIT_CORE, 0)?;
+
+            // 未开放非特权用户命名空间的系统上跳过
+            let mut isolated = false;
+            let mut bound = false;
+            if unsafe { libc::unshare(self.namespaces) } == 0 {
+                let _ = write_proc(c"/proc/self/setgroups", b"deny");
+                if write_proc(c"/proc/self/uid_map", &self.uid_map).is_ok()
+                    && write_proc(c"/proc/self/gid_map", &self.gid_map).is_ok()
+                {
+                    isolated = true;
+                    bound = self.bind_cwd_readonly();
+                }
+            }
+            // 命令不能向宿主的进程发送信号
+            if !isolated && !self.signals_scoped {
+                return Err(io::Error::from_raw_os_error(libc::ENOTSUP));
+            }
+
+            match self.landlock.take() {
+                // 命令按只读审批，规则必须完全生效
+                Some(ruleset) => match ruleset.restrict_self() {
+                    Ok(status) if status.ruleset == RulesetStatus::FullyEnforced => {}
+                    _ => return Err(io::Error::from_raw_os_error(libc::ENOTSUP)),
+                },
+                None if !bound => return Err(io::Error::from_raw_os_error(libc::ENOTSUP)),
+                None => {}
+            }
+
+            let program = libc::sock_fprog {
+                len: self.seccomp.len() as libc::c_ushort,
+                filter: self.seccomp.as_ptr() as *mut libc::sock_filter,
+            };
+            unsafe {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::syscall(libc::SYS_seccomp, libc::SECCOMP_SET_MODE_FILTER, 0, &program as *const libc::sock_fprog) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            if isolated {
+                enter_pid_namespace()?;
+            }
+            Ok(())
+        }
+
+        fn bind_cwd_readonly(&self) -> bool {
+            let cwd = self.cwd.as_ptr();
+            unsafe {
+                // 挂载变化不传播回宿主
+                libc::mount(std::ptr::null(), c"/".as_ptr(), std::ptr::null(), libc::MS_REC | libc::MS_PRIVATE, std::ptr::null()) == 0
+                    && libc::mount(cwd, cwd, std::ptr::null(), libc::MS_BIND | libc::MS_REC, std::ptr::null()) == 0
+                    && libc::mount(
+                        std::ptr::null(),
+                        cwd,
+                        std::ptr::null(),
+                        libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV,
+                        std::ptr::null(),
+                    ) == 0
+            }
+        }
+    }
+
+    // unshare 之后的第一个子进程才进入新的 PID 命名空间。子进程返回后继续 exec，
+    // 当前进程等待它结束并以同样的方式退出，spawn 看到的仍是一个普通子进程
+    fn enter_pid_namespace() -> io::Result<()> {
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if pid == 0 {
+            return Ok(());
+        }
+
+        unsafe {
+            // 关闭 spawn 的错误管道等描述符，exec 的结果由子进程报告
+            if libc::syscall(libc::SYS_close_range, 3, libc::c_uint::MAX, 0) != 0 {
+                for fd in 3..1024 {
+                    libc::close(fd);
+                }
+            }
+            let mut status = 0;
+            while libc::waitpid(pid, &mut status, 0) < 0 {
+                if io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                    libc::_exit(127);
+                }
+            }
+            if libc::WIFSIGNALED(status) {
+                let signal = libc::WTERMSIG(status);
+                libc::signal(signal, libc::SIG_DFL);
+                libc::kill(libc::getpid(), signal);
+            }
+            libc::_exit(libc::WEXITSTATUS(status));
+        }
+    }
+
+    fn set_limit(resource: Resource, value: u64) -> io::Result<()> {
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn write_proc(path: &CStr, content: &[u8]) -> io::Result<()> {
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, content.as_ptr() as *const libc::c_void, content.len());
+            libc::close(fd);
+            if written != content.len() as isize {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    // RLIMIT_NPROC 按用户统计，需要在已有进程数的基础上放宽
+    fn count_user_processes(uid: libc::uid_t) -> u64 {
+        std::fs::read_dir("/proc")
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter(|entry| entry.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+                    .filter(|entry| entry.metadata().map(|m| m.uid() == uid).unwrap_or(false))
+                    .count() as u64
+            })
+            .unwrap_or(0)
+    }
+
+    // 内核不支持所需的 landlock 版本时返回错误，不降级。
+    // 第二个返回值表示是否同时限制了信号和抽象 unix 套接字（需要 ABI v6）
+    pub(super) fn landlock_ruleset(cwd: &Path, scratch: &Path) -> Result<(RulesetCreated, bool), String> {
+        match build_ruleset(cwd, scratch, true) {
+            Ok(ruleset) => Ok((ruleset, true)),
+            Err(_) => build_ruleset(cwd, scratch, false).map(|ruleset| (ruleset, false)),
+        }
+    }
+
+    fn build_ruleset(cwd: &Path, scratch: &Path, scoped: bool) -> Result<RulesetCreated, String> {
+        let abi = ABI::V3;
+        let readable = SYSTEM_PATHS.iter().map(Path::new).chain([cwd]);
+        let ruleset = Ruleset::default()
+            .set_compatibility(CompatLevel::HardRequirement)
+            .handle_access(AccessFs::from_all(abi))
+            .and_then(|ruleset| if scoped { ruleset.scope(Scope::from_all(ABI::V6)) } else { Ok(ruleset) })
+            .and_then(|ruleset| ruleset.create())
+            .and_then(|ruleset| ruleset.add_rules(path_beneath_rules(readable, AccessFs::from_read(abi))))
+            .and_then(|ruleset| ruleset.add_rules(path_beneath_rules([scratch], AccessFs::from_all(abi))))
+            .and_then(|ruleset| ruleset.add_rules(path_beneath_rules(["/dev/null"], AccessFs::from_all(abi))));
+        ruleset.map_err(|e| format!("Failed to prepare landlock rules: {}", e))
+    }
+
+    fn seccomp_filter(allow_network: bool) -> Result<BpfProgram, String> {
+        let mut rules: BTreeMap<i64, Vec<SeccompRule>> = DENIED_SYSCALLS
+            .iter()
+            .map(|syscall| (*syscall, Vec::new()))
+            .collect();
+
+        let mut families = LOCAL_FAMILIES.to_vec();
+        if !allow_network {
+            families.extend_from_slice(NETWORK_FAMILIES);
+        }
+        let socket_rules = families
+            .iter()
+            .map(|family| {
+                SeccompCondition::new(0, SeccompCmpArgLen::Dword, SeccompCmpOp::Eq, *family as u64)
+                    .and_then(|condition| SeccompRule::new(vec![condition]))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to build seccomp rules: {}", e))?;
+        rules.insert(libc::SYS_socket, socket_rules);
+
+        let arch = TargetArch::try_from(std::env::consts::ARCH)
+            .map_err(|e| format!("Unsupported architecture for seccomp: {}", e))?;
+        let filter = SeccompFilter::new(rules, SeccompAction::Allow, SeccompAction::Errno(libc::EPERM as u32), arch)
+            .map_err(|e| format!("Failed to build seccomp filter: {}", e))?;
+        filter.try_into().map_err(|e| format!("Failed to compile seccomp filter: {}", e))
+    }
+
+    fn pipe() -> Result<(OwnedFd, OwnedFd), String> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(format!("Failed to create pipe: {}", io::Error::last_os_error()));
+        }
+        Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+    }
+
+    // 读取输出直到所有写端关闭，超出上限的部分丢弃
+    fn read_output(reader: OwnedFd, output: Arc<Mutex<(Vec<u8>, bool)>>) {
+        let mut file = File::from(reader);
+        let mut buffer = [0u8; 8192];
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let mut output = output.lock().unwrap();
+                    let room = MAX_OUTPUT_BYTES.saturating_sub(output.0.len());
+                    output.0.extend_from_slice(&buffer[..n.min(room)]);
+                    if n > room {
+                        output.1 = true;
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn run_confined(command: &str, cwd: &Path, scratch: &Path, options: &SandboxOptions) -> Result<SandboxResult, String> {
+        let mut confinement = Confinement::prepare(cwd, scratch, options)?;
+        let (reader, writer) = pipe()?;
+        let stderr_writer = writer.try_clone().map_err(|e| format!("Failed to create pipe: {}", e))?;
+
+        let mut cmd = tokio::process::Command::new("/bin/sh");
+        cmd.env_clear();
+        for name in ENV_ALLOWLIST {
+            if let Some(value) = std::env::var_os(name) {
+                cmd.env(name, value);
+            }
+        }
+        cmd.arg("-c")
+            .arg(command)
+            .current_dir(cwd)
+            .env("TMPDIR", scratch)
+            .env("CHATSHELL_SANDBOX", "1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::from(writer))
+            .stderr(Stdio::from(stderr_writer))
+            .kill_on_drop(true);
+        unsafe {
+            cmd.pre_exec(move || confinement.apply());
+        }
+
+        let started_at = Instant::now();
+        let mut child = cmd.spawn().map_err(|e| match e.raw_os_error() {
+            Some(libc::ENOTSUP) => "Sandbox cannot isolate the command on this system, refusing to run it".to_string(),
+            _ => format!("Failed to start sandboxed command: {}", e),
+        })?;
+        // 关闭父进程持有的写端，子进程退出后读取才会结束
+        drop(cmd);
+        let pgid = child.id().map(|pid| pid as libc::pid_t);
+
+        let output = Arc::new(Mutex::new((Vec::new(), false)));
+        let reader_output = output.clone();
+        let reader_task = tokio::task::spawn_blocking(move || read_output(reader, reader_output));
+
+        let (status, timed_out) = match tokio::time::timeout(Duration::from_millis(options.timeout_ms), child.wait()).await {
+            Ok(status) => (Some(status.map_err(|e| format!("Failed to wait for sandboxed command: {}", e))?), false),
+            Err(_) => (None, true),
+        };
+        let duration_ms = started_at.elapsed().as_millis() as u64;
+
+        // 结束整个进程组，包括留在后台的子进程
+        if let Some(pgid) = pgid {
+            unsafe {
+                libc::kill(-pgid, libc::SIGKILL);
+            }
+        }
+        let status = match status {
+            Some(status) => Some(status),
+            None => child.wait().await.ok(),
+        };
+        let _ = tokio::time::timeout(Duration::from_millis(OUTPUT_DRAIN_MS), reader_task).await;
+
+        let (bytes, truncated) = output.lock().unwrap().clone();
+        Ok(SandboxResult {
+            output: String::from_utf8_lossy(&bytes).into_owned(),
+            exit_code: status.and_then(|s| s.code()),
+            signal: if timed_out { None } else { status.and_then(|s| s.signal()) },
+            timed_out,
+            truncated,
+            duration_ms,
+        })
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // 内核不支持 landlock 时跳过
+    async fn run_in(cwd: &Path, command: &str) -> Option<SandboxResult> {
+        if !filesystem_confined() {
+            eprintln!("landlock unavailable, skipping");
+            return None;
+        }
+        Some(run(command, cwd, &SandboxOptions::default()).await.unwrap())
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chatshell-test-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn reads_cwd_and_system_directories() {
+        let cwd = temp_dir("cwd");
+        std::fs::write(cwd.join("notes.txt"), "hello").unwrap();
+
+        if let Some(result) = run_in(&cwd, "cat notes.txt && head -c 0 /etc/passwd && ls /usr/bin >/dev/null").await {
+            assert_eq!(result.exit_code, Some(0), "{}", result.output);
+            assert_eq!(result.output, "hello");
+        }
+        std::fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[tokio::test]
+    async fn denies_paths_outside_cwd() {
+        let cwd = temp_dir("cwd");
+        let other = temp_dir("other");
+        std::fs::write(other.join("secret.txt"), "secret").unwrap();
+
+        let command = format!("cat {}/secret.txt; touch notes.txt", other.display());
+        if let Some(result) = run_in(&cwd, &command).await {
+            assert_ne!(result.exit_code, Some(0));
+            assert!(result.output.contains("Permission denied"), "{}", result.output);
+            assert!(!cwd.join("notes.txt").exists());
+        }
+        std::fs::remove_dir_all(&cwd).unwrap();
+        std::fs::remove_dir_all(&other).unwrap();
+    }
+
+    #[tokio::test]
+    async fn isolates_processes_and_environment() {
+        let cwd = temp_dir("cwd");
+        std::env::set_var("CHATSHELL_TEST_TOKEN", "leaked");
+
+        if let Some(result) = run_in(&cwd, "kill -9 -1; echo \"token=$CHATSHELL_TEST_TOKEN\"").await {
+            assert!(result.output.ends_with("token=\n"), "{}", result.output);
+        }
+        std::fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_home_as_cwd() {
+        let Some(home) = std::env::var_os("HOME").map(PathBuf::from) else {
+            return;
+        };
+        assert!(run("ls", &home, &SandboxOptions::default()).await.is_err());
+        assert!(run("ls", Path::new("/"), &SandboxOptions::default()).await.is_err());
+    }
+}
@@ -0,0 +1,105 @@
+// src/screen.rs - 无界面的虚拟终端，用于生成纯文本屏幕快照
+
+use serde::Serialize;
+
+pub const DEFAULT_SCREEN_SCROLLBACK: usize = 1000;
+
+pub struct VirtualScreen {
+    parser: vt100::Parser,
+}
+
+// 屏幕快照，行内容不含任何转义序列
+#[derive(Debug, Clone, Serialize)]
+pub struct ScreenSnapshot {
+    pub rows: u16,
+    pub cols: u16,
+    pub cursor_row: u16,
+    pub cursor_col: u16,
+    pub alternate_screen: bool,
+    pub title: String,
+    pub lines: Vec<String>,
+    pub text: String,
+    pub scrollback: Vec<String>,
+}
+
+impl VirtualScreen {
+    pub fn new(rows: u16, cols: u16, scrollback_len: usize) -> Self {
+        Self {
+            parser: vt100::Parser::new(rows, cols, scrollback_len),
+        }
+    }
+
+    pub fn process(&mut self, output: &str) {
+        self.parser.process(output.as_bytes());
+    }
+
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        self.parser.set_size(rows, cols);
+    }
+
+    // 当前可见屏幕的纯文本
+    pub fn text(&self) -> String {
+        self.visible_lines().join("\n").trim_end().to_string()
+    }
+
+    fn visible_lines(&self) -> Vec<String> {
+        let screen = self.parser.screen();
+        let (_, cols) = screen.size();
+        screen.rows(0, cols).map(|row| row.trim_end().to_string()).collect()
+    }
+
+    // 滚出屏幕的最近 limit 行，按从旧到新排列
+    pub fn scrollback_lines(&mut self, limit: usize) -> Vec<String> {
+        self.parser.set_scrollback(usize::MAX);
+        let total = self.parser.screen().scrollback();
+        let cols = self.parser.screen().size().1;
+
+        // 向上滚动 k 行时，屏幕第一行就是倒数第 k 行滚动历史
+        let mut lines = Vec::with_capacity(limit.min(total));
+        for offset in (1..=limit.min(total)).rev() {
+            self.parser.set_scrollback(offset);
+            if let Some(row) = self.parser.screen().rows(0, cols).next() {
+                lines.push(row.trim_end().to_string());
+            }
+        }
+
+        self.parser.set_scrollback(0);
+        lines
+    }
+
+    pub fn snapshot(&mut self, scrollback_limit: usize) -> ScreenSnapshot {
+        let scrollback = self.scrollback_lines(scrollback_limit);
+        let screen = self.parser.screen();
+        let (rows, cols) = screen.size();
+        let (cursor_row, cursor_col) = screen.cursor_position();
+        let lines = self.visible_lines();
+
+        ScreenSnapshot {
+            rows,
+            cols,
+            cursor_row,
+            cursor_col,
+            alternate_screen: screen.alternate_screen(),
+            title: screen.title().to_string(),
+            text: lines.join("\n").trim_end().to_string(),
+            lines,
+            scrollback,
+        }
+    }
+}
+
+// 将一段原始输出按终端宽度渲染成纯文本，用于返回命令执行结果
+pub fn render_plain(output: &str, cols: u16) -> String {
+    let cols = cols.max(1);
+    // 预留自动换行产生的额外行
+    let line_count = output.matches('\n').count() + output.len() / cols as usize + 1;
+    let mut screen = VirtualScreen::new(24, cols, line_count);
+    screen.process(output);
+
+    let mut lines = screen.scrollback_lines(line_count);
+    lines.extend(screen.visible_lines());
+    while lines.last().map(|line| line.is_empty()).unwrap_or(false) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,111 @@
+// src/scrollback.rs - 会话输出的有界环形缓冲区
+
+use serde::Serialize;
+use std::collections::VecDeque;
+
+pub const DEFAULT_SCROLLBACK_LINES: usize = 10_000;
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 4 * 1024 * 1024;
+
+// 按行保存输出，超过行数或字节上限时丢弃最早的行
+// 每一行都有一个全局递增的偏移量，被丢弃后偏移量不会复用
+pub struct ScrollbackBuffer {
+    lines: VecDeque<String>,
+    partial: String,
+    first_offset: u64,
+    bytes: usize,
+    max_lines: usize,
+    max_bytes: usize,
+}
+
+// 一段按偏移量读取的输出
+#[derive(Debug, Clone, Serialize)]
+pub struct ScrollbackRange {
+    pub first_offset: u64,
+    pub lines: Vec<String>,
+}
+
+impl ScrollbackBuffer {
+    pub fn new(max_lines: usize, max_bytes: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            partial: String::new(),
+            first_offset: 0,
+            bytes: 0,
+            max_lines: max_lines.max(1),
+            max_bytes: max_bytes.max(1),
+        }
+    }
+
+    pub fn push(&mut self, text: &str) {
+        let mut rest = text;
+        while let Some(pos) = rest.find('\n') {
+            self.partial.push_str(&rest[..pos]);
+            let mut line = std::mem::take(&mut self.partial);
+            if line.ends_with('\r') {
+                line.pop();
+            }
+            self.bytes += line.len();
+            self.lines.push_back(line);
+            rest = &rest[pos + 1..];
+        }
+        self.partial.push_str(rest);
+
+        // 未换行的超长输出（如进度条）也要受字节上限约束
+        if self.partial.len() > self.max_bytes {
+            let mut cut = self.partial.len() - self.max_bytes;
+            while !self.partial.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.partial.drain(..cut);
+        }
+
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.lines.len() > self.max_lines
+            || (self.bytes + self.partial.len() > self.max_bytes && !self.lines.is_empty())
+        {
+            if let Some(line) = self.lines.pop_front() {
+                self.bytes -= line.len();
+                self.first_offset += 1;
+            }
+        }
+    }
+
+    // 下一行将要使用的偏移量
+    pub fn end_offset(&self) -> u64 {
+        self.first_offset + self.lines.len() as u64
+    }
+
+    pub fn first_offset(&self) -> u64 {
+        self.first_offset
+    }
+
+    // 最近 n 行，包含尚未换行的最后一行
+    pub fn last_lines(&self, count: usize) -> Vec<String> {
+        // count 来自前端和模型，不能直接用来分配
+        let mut result: Vec<String> = Vec::with_capacity(count.min(self.lines.len() + 1));
+        let mut remaining = count;
+        if !self.partial.is_empty() && remaining > 0 {
+            remaining -= 1;
+        }
+        let skip = self.lines.len().saturating_sub(remaining);
+        result.extend(self.lines.iter().skip(skip).cloned());
+        if !self.partial.is_empty() && count > 0 {
+            result.push(self.partial.clone());
+        }
+        result
+    }
+
+    // 从 offset 开始读取 count 行，已被丢弃的部分会被跳过
+    pub fn range(&self, offset: u64, count: usize) -> ScrollbackRange {
+        let start = offset.max(self.first_offset);
+        let index = (start - self.first_offset) as usize;
+        let lines = self.lines.iter().skip(index).take(count).cloned().collect();
+        ScrollbackRange {
+            first_offset: start,
+            lines,
+        }
+    }
+}
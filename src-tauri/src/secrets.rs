@@ -0,0 +1,196 @@
+// src/secrets.rs - API key 的安全存储
+//
+// 优先使用系统密钥环（Linux 上为 Secret Service，macOS 为钥匙串，Windows 为凭据管理器），
+// 在没有桌面会话的环境中退回到应用配置目录下的文件：secrets.enc 保存用 AES-256-GCM 加密的
+// { 配置名: 密文 }，密钥保存在同一目录的 secrets.key 中（权限 0600）。
+// 能读取配置目录的人同样能读取密钥，所以这只是混淆，避免 key 以明文出现在备份、日志或
+// 同步的配置中，安全性依赖文件权限。使用这种方式时会提示用户，见 file_fallback_used。
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const KEYRING_SERVICE: &str = "chatshell";
+// 与密文放在一起的混淆密钥，不是真正的主密钥
+const OBFUSCATION_KEY_FILE: &str = "secrets.key";
+const SECRETS_FILE: &str = "secrets.enc";
+const NONCE_LEN: usize = 12;
+
+// 加密文件所在目录，未初始化时只能使用系统密钥环
+static FALLBACK_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+// 是否有 key 保存在文件中而不是系统密钥环
+static FILE_FALLBACK_USED: AtomicBool = AtomicBool::new(false);
+
+pub fn init(dir: PathBuf) {
+    *FALLBACK_DIR.lock().unwrap() = Some(dir);
+}
+
+// 保存 API key，空字符串表示删除
+pub fn store_key(profile: &str, key: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return delete_key(profile);
+    }
+
+    match keyring_entry(profile).and_then(|entry| entry.set_password(key).map_err(|e| e.to_string())) {
+        Ok(()) => {
+            // 之前可能保存在加密文件中
+            let _ = remove_from_file(profile);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("System keyring unavailable ({}), API key is only obfuscated on disk", e);
+            write_to_file(profile, Some(key))
+        }
+    }
+}
+
+pub fn load_key(profile: &str) -> Result<Option<String>, String> {
+    match keyring_entry(profile).map(|entry| entry.get_password()) {
+        Ok(Ok(key)) => return Ok(Some(key)),
+        Ok(Err(keyring::Error::NoEntry)) => {}
+        Ok(Err(e)) => println!("[RUST] System keyring unavailable ({}), reading obfuscated key file", e),
+        Err(e) => println!("[RUST] System keyring unavailable ({}), reading obfuscated key file", e),
+    }
+    read_from_file(profile)
+}
+
+pub fn delete_key(profile: &str) -> Result<(), String> {
+    if let Ok(entry) = keyring_entry(profile) {
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => eprintln!("Failed to delete key from system keyring: {}", e),
+        }
+    }
+    remove_from_file(profile)
+}
+
+// API key 只做了混淆、没有受到系统密钥环保护时，界面需要提示用户
+pub fn file_fallback_used() -> bool {
+    FILE_FALLBACK_USED.load(Ordering::Relaxed)
+}
+
+// 用于界面展示的脱敏形式，只保留末尾 4 个字符
+pub fn redact(key: &str) -> Option<String> {
+    if key.is_empty() {
+        return None;
+    }
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return Some("****".to_string());
+    }
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    Some(format!("****{}", tail))
+}
+
+fn keyring_entry(profile: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, profile).map_err(|e| e.to_string())
+}
+
+fn fallback_dir() -> Result<PathBuf, String> {
+    FALLBACK_DIR.lock().unwrap()
+        .clone()
+        .ok_or_else(|| "Secret storage not initialized".to_string())
+}
+
+// 以 0600 权限写入，避免其他用户读取
+pub(crate) fn write_private(path: &Path, content: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&tmp)
+        .map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    file.write_all(content).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn obfuscation_key(dir: &Path) -> Result<Key<Aes256Gcm>, String> {
+    let path = dir.join(OBFUSCATION_KEY_FILE);
+    match fs::read(&path) {
+        Ok(bytes) if bytes.len() == 32 => Ok(*Key::<Aes256Gcm>::from_slice(&bytes)),
+        Ok(_) => Err(format!("Invalid key in {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = Aes256Gcm::generate_key(OsRng);
+            write_private(&path, key.as_slice())?;
+            Ok(key)
+        }
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+fn read_file_entries(dir: &Path) -> Result<HashMap<String, String>, String> {
+    match fs::read_to_string(dir.join(SECRETS_FILE)) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| format!("Failed to parse secrets file: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(format!("Failed to read secrets file: {}", e)),
+    }
+}
+
+fn write_to_file(profile: &str, key: Option<&str>) -> Result<(), String> {
+    let dir = fallback_dir()?;
+    let mut entries = read_file_entries(&dir)?;
+
+    match key {
+        Some(key) => {
+            let cipher = Aes256Gcm::new(&obfuscation_key(&dir)?);
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let ciphertext = cipher.encrypt(&nonce, key.as_bytes())
+                .map_err(|_| "Failed to encrypt API key".to_string())?;
+            let mut sealed = nonce.to_vec();
+            sealed.extend_from_slice(&ciphertext);
+            entries.insert(profile.to_string(), BASE64.encode(sealed));
+            FILE_FALLBACK_USED.store(true, Ordering::Relaxed);
+        }
+        None => {
+            if entries.remove(profile).is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    let content = serde_json::to_vec_pretty(&entries).map_err(|e| e.to_string())?;
+    write_private(&dir.join(SECRETS_FILE), &content)
+}
+
+fn remove_from_file(profile: &str) -> Result<(), String> {
+    match fallback_dir() {
+        Ok(_) => write_to_file(profile, None),
+        Err(_) => Ok(()),
+    }
+}
+
+fn read_from_file(profile: &str) -> Result<Option<String>, String> {
+    let dir = match fallback_dir() {
+        Ok(dir) => dir,
+        Err(_) => return Ok(None),
+    };
+    let sealed = match read_file_entries(&dir)?.remove(profile) {
+        Some(sealed) => BASE64.decode(sealed).map_err(|e| format!("Corrupt secrets file: {}", e))?,
+        None => return Ok(None),
+    };
+    if sealed.len() <= NONCE_LEN {
+        return Err("Corrupt secrets file".to_string());
+    }
+
+    let cipher = Aes256Gcm::new(&obfuscation_key(&dir)?);
+    FILE_FALLBACK_USED.store(true, Ordering::Relaxed);
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Failed to decrypt API key".to_string())?;
+    String::from_utf8(plaintext)
+        .map(Some)
+        .map_err(|_| "Failed to decrypt API key".to_string())
+}
@@ -0,0 +1,520 @@
+// src/shell_integration.rs - Shell 集成：注入 OSC 133 / OSC 7 标记并从输出中解析
+//
+// 任何程序都可以向 PTY 输出同样的转义序列，所以每个会话生成一个 nonce，
+// 通过 CHATSHELL_NONCE 交给集成脚本。脚本读取后立即 unset，避免传给子进程，
+// 并在每个标记末尾附加 `;<nonce>`，解析时忽略 nonce 不匹配的标记。
+
+use portable_pty::CommandBuilder;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// OSC 负载的最大长度，超出后按普通输出处理，避免异常数据无限缓存
+const MAX_OSC_LEN: usize = 8192;
+
+const BASH_SCRIPT: &str = r#"# chatshell shell integration (bash)
+__chatshell_nonce=$CHATSHELL_NONCE
+unset CHATSHELL_NONCE
+if [ -f ~/.bashrc ]; then . ~/.bashrc; fi
+
+if [ -z "$__CHATSHELL_INTEGRATED" ]; then
+__CHATSHELL_INTEGRATED=1
+__chatshell_ready=0
+__chatshell_running=0
+__chatshell_last_hist=
+
+__chatshell_preexec() {
+    [ "$__chatshell_ready" = 1 ] || return
+    [ -n "$COMP_LINE" ] && return
+    case "$BASH_COMMAND" in __chatshell_*) return ;; esac
+    __chatshell_ready=0
+    __chatshell_running=1
+    local hist cmd
+    hist=$(HISTTIMEFORMAT= builtin history 1 2>/dev/null)
+    if [ -n "$hist" ] && [ "$hist" != "$__chatshell_last_hist" ] \
+        && [[ $hist =~ ^[[:space:]]*[0-9]+[*[:space:]]+(.*)$ ]]; then
+        cmd=${BASH_REMATCH[1]}
+    else
+        cmd=$BASH_COMMAND
+    fi
+    printf '\e]633;E;%s;%s\a\e]133;C;%s\a' "$cmd" "$__chatshell_nonce" "$__chatshell_nonce"
+}
+
+__chatshell_precmd() {
+    local ret=$?
+    __chatshell_ready=0
+    if [ "$__chatshell_running" = 1 ]; then
+        printf '\e]133;D;%s;%s\a' "$ret" "$__chatshell_nonce"
+        __chatshell_running=0
+    fi
+    printf '\e]7;file://%s%s;%s\a\e]133;A;%s\a' "$HOSTNAME" "$PWD" "$__chatshell_nonce" "$__chatshell_nonce"
+    __chatshell_last_hist=$(HISTTIMEFORMAT= builtin history 1 2>/dev/null)
+    return $ret
+}
+
+# 用户的 PROMPT_COMMAND 执行完毕后才开始捕获下一条命令
+trap '__chatshell_preexec' DEBUG
+__chatshell_user_pc=$PROMPT_COMMAND
+while [[ $__chatshell_user_pc == *[\;[:space:]] ]]; do __chatshell_user_pc=${__chatshell_user_pc%?}; done
+PROMPT_COMMAND="__chatshell_precmd${__chatshell_user_pc:+; $__chatshell_user_pc}; __chatshell_ready=1"
+unset __chatshell_user_pc
+PS1="${PS1}\[\e]133;B;${__chatshell_nonce}\a\]"
+fi
+"#;
+
+const ZSH_ENV_SCRIPT: &str = r#"# chatshell shell integration (zsh)
+__chatshell_nonce=$CHATSHELL_NONCE
+unset CHATSHELL_NONCE
+__chatshell_zdotdir=$ZDOTDIR
+ZDOTDIR=${CHATSHELL_USER_ZDOTDIR:-$HOME}
+if [[ -f "$ZDOTDIR/.zshenv" ]]; then
+    source "$ZDOTDIR/.zshenv"
+fi
+CHATSHELL_USER_ZDOTDIR=$ZDOTDIR
+ZDOTDIR=$__chatshell_zdotdir
+unset __chatshell_zdotdir
+"#;
+
+const ZSH_RC_SCRIPT: &str = r#"# chatshell shell integration (zsh)
+ZDOTDIR=${CHATSHELL_USER_ZDOTDIR:-$HOME}
+unset CHATSHELL_USER_ZDOTDIR
+if [[ -f "$ZDOTDIR/.zshrc" ]]; then
+    source "$ZDOTDIR/.zshrc"
+fi
+
+if [[ -z "$__CHATSHELL_INTEGRATED" ]]; then
+__CHATSHELL_INTEGRATED=1
+__chatshell_running=0
+
+__chatshell_precmd() {
+    local ret=$?
+    if (( __chatshell_running )); then
+        printf '\e]133;D;%s;%s\a' "$ret" "$__chatshell_nonce"
+        __chatshell_running=0
+    fi
+    printf '\e]7;file://%s%s;%s\a\e]133;A;%s\a' "$HOST" "$PWD" "$__chatshell_nonce" "$__chatshell_nonce"
+    return $ret
+}
+
+__chatshell_preexec() {
+    __chatshell_running=1
+    printf '\e]633;E;%s;%s\a\e]133;C;%s\a' "$1" "$__chatshell_nonce" "$__chatshell_nonce"
+}
+
+precmd_functions=(__chatshell_precmd $precmd_functions)
+preexec_functions+=(__chatshell_preexec)
+PS1="${PS1}%{$(printf '\e]133;B;%s\a' "$__chatshell_nonce")%}"
+fi
+"#;
+
+const FISH_SCRIPT: &str = r#"# chatshell shell integration (fish)
+if not set -q __CHATSHELL_INTEGRATED
+    set -g __CHATSHELL_INTEGRATED 1
+    set -g __chatshell_nonce $CHATSHELL_NONCE
+    set -e CHATSHELL_NONCE
+
+    function __chatshell_preexec --on-event fish_preexec
+        printf '\e]633;E;%s;%s\a\e]133;C;%s\a' "$argv" $__chatshell_nonce $__chatshell_nonce
+    end
+
+    function __chatshell_postexec --on-event fish_postexec
+        printf '\e]133;D;%s;%s\a' $status $__chatshell_nonce
+    end
+
+    function __chatshell_prompt --on-event fish_prompt
+        printf '\e]7;file://%s%s;%s\a\e]133;A;%s\a' $hostname "$PWD" $__chatshell_nonce $__chatshell_nonce
+    end
+end
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl ShellKind {
+    pub fn detect(shell: &str) -> Option<Self> {
+        let name = Path::new(shell).file_name()?.to_str()?;
+        match name.trim_start_matches('-') {
+            "bash" => Some(ShellKind::Bash),
+            "zsh" => Some(ShellKind::Zsh),
+            "fish" => Some(ShellKind::Fish),
+            _ => None,
+        }
+    }
+}
+
+// Shell 集成上报的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellEvent {
+    // OSC 133;A 提示符开始
+    PromptStart,
+    // OSC 133;B 提示符结束，开始输入命令
+    CommandInputStart,
+    // OSC 633;E 即将执行的命令行
+    CommandLine(String),
+    // OSC 133;C 命令开始执行
+    CommandExecuted,
+    // OSC 133;D 命令结束及退出码
+    CommandFinished(Option<i32>),
+    // OSC 7 当前工作目录
+    Cwd(String),
+}
+
+// 解析结果：普通输出和事件按原始顺序排列
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellOutput {
+    Text(String),
+    Event(ShellEvent),
+}
+
+// 为支持的 shell 注入集成脚本，返回是否注入成功。nonce 与会话的 OscParser 相同
+pub fn inject(shell: &str, cmd: &mut CommandBuilder, env: &HashMap<String, String>, nonce: &str) -> Result<bool, String> {
+    let kind = match ShellKind::detect(shell) {
+        Some(kind) => kind,
+        None => return Ok(false),
+    };
+    cmd.env("CHATSHELL_NONCE", nonce);
+
+    let dir = integration_dir()?;
+    match kind {
+        ShellKind::Bash => {
+            let rcfile = write_script(&dir, "bashrc", BASH_SCRIPT)?;
+            cmd.arg("--rcfile");
+            cmd.arg(rcfile);
+        }
+        ShellKind::Zsh => {
+            let zdotdir = dir.join("zsh");
+            write_script(&zdotdir, ".zshenv", ZSH_ENV_SCRIPT)?;
+            write_script(&zdotdir, ".zshrc", ZSH_RC_SCRIPT)?;
+            let user_zdotdir = env.get("ZDOTDIR")
+                .or_else(|| env.get("HOME"))
+                .cloned()
+                .unwrap_or_default();
+            cmd.env("CHATSHELL_USER_ZDOTDIR", user_zdotdir);
+            cmd.env("ZDOTDIR", zdotdir);
+        }
+        ShellKind::Fish => {
+            let script = write_script(&dir, "chatshell.fish", FISH_SCRIPT)?;
+            cmd.arg("--init-command");
+            cmd.arg(format!("source '{}'", script.to_string_lossy().replace('\'', "\\'")));
+        }
+    }
+    Ok(true)
+}
+
+// 脚本会在用户的 shell 中执行，只能放在当前用户私有的目录中：
+// 优先使用 $XDG_RUNTIME_DIR，否则使用用户缓存目录
+fn integration_dir() -> Result<PathBuf, String> {
+    let base = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(dirs::cache_dir)
+        .ok_or_else(|| "Cannot determine a private directory for shell integration".to_string())?;
+    let dir = base.join("chatshell-shell-integration");
+    create_private_dir(&dir)?;
+    Ok(dir)
+}
+
+// 目录必须属于当前用户，权限收紧为 0700
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| format!("Failed to create shell integration dir: {}", e))?;
+    let metadata = std::fs::symlink_metadata(dir)
+        .map_err(|e| format!("Failed to inspect shell integration dir: {}", e))?;
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } {
+        return Err(format!("Shell integration dir {} is not owned by the current user", dir.display()));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .map_err(|e| format!("Failed to restrict shell integration dir: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create shell integration dir: {}", e))
+}
+
+fn write_script(dir: &Path, name: &str, content: &str) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create shell integration dir: {}", e))?;
+    let path = dir.join(name);
+    std::fs::write(&path, content)
+        .map_err(|e| format!("Failed to write shell integration script: {}", e))?;
+    Ok(path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParserState {
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+// 流式 OSC 解析器，标记可能跨多个输出块
+pub struct OscParser {
+    state: ParserState,
+    payload: String,
+    // 集成脚本附加在标记末尾的会话 nonce
+    nonce: String,
+}
+
+impl OscParser {
+    pub fn new(nonce: String) -> Self {
+        Self {
+            state: ParserState::Ground,
+            payload: String::new(),
+            nonce,
+        }
+    }
+
+    pub fn feed(&mut self, input: &str) -> Vec<ShellOutput> {
+        let mut result = Vec::new();
+        let mut text = String::new();
+
+        for ch in input.chars() {
+            match self.state {
+                ParserState::Ground => {
+                    if ch == '\x1b' {
+                        self.state = ParserState::Escape;
+                    } else {
+                        text.push(ch);
+                    }
+                }
+                ParserState::Escape => {
+                    if ch == ']' {
+                        self.state = ParserState::Osc;
+                        self.payload.clear();
+                    } else {
+                        text.push('\x1b');
+                        if ch == '\x1b' {
+                            continue;
+                        }
+                        text.push(ch);
+                        self.state = ParserState::Ground;
+                    }
+                }
+                ParserState::Osc => {
+                    if ch == '\x07' {
+                        self.finish_osc("\x07", &mut text, &mut result);
+                    } else if ch == '\x1b' {
+                        self.state = ParserState::OscEscape;
+                    } else if self.payload.len() >= MAX_OSC_LEN {
+                        text.push_str("\x1b]");
+                        text.push_str(&self.payload);
+                        text.push(ch);
+                        self.payload.clear();
+                        self.state = ParserState::Ground;
+                    } else {
+                        self.payload.push(ch);
+                    }
+                }
+                ParserState::OscEscape => {
+                    if ch == '\\' {
+                        self.finish_osc("\x1b\\", &mut text, &mut result);
+                    } else {
+                        // 不完整的 OSC，原样输出
+                        text.push_str("\x1b]");
+                        text.push_str(&self.payload);
+                        self.payload.clear();
+                        match ch {
+                            ']' => self.state = ParserState::Osc,
+                            '\x1b' => {
+                                text.push('\x1b');
+                                self.state = ParserState::Escape;
+                            }
+                            _ => {
+                                text.push('\x1b');
+                                text.push(ch);
+                                self.state = ParserState::Ground;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            result.push(ShellOutput::Text(text));
+        }
+        result
+    }
+
+    fn finish_osc(&mut self, terminator: &str, text: &mut String, result: &mut Vec<ShellOutput>) {
+        let payload = std::mem::take(&mut self.payload);
+        self.state = ParserState::Ground;
+
+        match parse_osc(&payload, &self.nonce) {
+            Some(event) => {
+                if !text.is_empty() {
+                    result.push(ShellOutput::Text(std::mem::take(text)));
+                }
+                result.push(ShellOutput::Event(event));
+            }
+            None => {
+                // 其他 OSC（如窗口标题）交给前端处理
+                text.push_str("\x1b]");
+                text.push_str(&payload);
+                text.push_str(terminator);
+            }
+        }
+    }
+}
+
+fn parse_osc(payload: &str, nonce: &str) -> Option<ShellEvent> {
+    // 没有 nonce 的标记可能来自命令输出，按普通 OSC 处理
+    let (payload, marker_nonce) = payload.rsplit_once(';')?;
+    if nonce.is_empty() || marker_nonce != nonce {
+        return None;
+    }
+
+    if let Some(rest) = payload.strip_prefix("133;") {
+        let mut parts = rest.splitn(2, ';');
+        return match parts.next()? {
+            "A" => Some(ShellEvent::PromptStart),
+            "B" => Some(ShellEvent::CommandInputStart),
+            "C" => Some(ShellEvent::CommandExecuted),
+            "D" => Some(ShellEvent::CommandFinished(
+                parts.next().and_then(|code| code.trim().parse().ok()),
+            )),
+            _ => None,
+        };
+    }
+
+    if let Some(command) = payload.strip_prefix("633;E;") {
+        return Some(ShellEvent::CommandLine(command.to_string()));
+    }
+
+    if let Some(url) = payload.strip_prefix("7;") {
+        let path = url.strip_prefix("file://")?;
+        // 跳过主机名部分
+        let path = &path[path.find('/')?..];
+        return Some(ShellEvent::Cwd(percent_decode(path)));
+    }
+
+    None
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                decoded.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> ShellOutput {
+        ShellOutput::Text(s.to_string())
+    }
+
+    fn event(event: ShellEvent) -> ShellOutput {
+        ShellOutput::Event(event)
+    }
+
+    #[test]
+    fn parses_markers_in_order() {
+        let mut parser = OscParser::new("n0nce".to_string());
+        assert_eq!(
+            parser.feed("\x1b]133;A;n0nce\x07$ \x1b]133;B;n0nce\x07ls\r\n\x1b]633;E;ls -la;n0nce\x07\x1b]133;C;n0nce\x07out\x1b]133;D;0;n0nce\x07"),
+            vec![
+                event(ShellEvent::PromptStart),
+                text("$ "),
+                event(ShellEvent::CommandInputStart),
+                text("ls\r\n"),
+                event(ShellEvent::CommandLine("ls -la".to_string())),
+                event(ShellEvent::CommandExecuted),
+                text("out"),
+                event(ShellEvent::CommandFinished(Some(0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_markers_split_across_chunks() {
+        let mut parser = OscParser::new("n0nce".to_string());
+        assert_eq!(parser.feed("out\x1b]13"), vec![text("out")]);
+        assert_eq!(parser.feed("3;D;1"), vec![]);
+        assert_eq!(parser.feed("27;n0nce\x07rest"), vec![event(ShellEvent::CommandFinished(Some(127))), text("rest")]);
+
+        assert_eq!(parser.feed("a\x1b"), vec![text("a")]);
+        assert_eq!(parser.feed("]133;A;n0nce\x07"), vec![event(ShellEvent::PromptStart)]);
+
+        // ST 结束符被拆开
+        assert_eq!(parser.feed("\x1b]7;file://host/tmp/a%20b;n0nce\x1b"), vec![]);
+        assert_eq!(parser.feed("\\"), vec![event(ShellEvent::Cwd("/tmp/a b".to_string()))]);
+    }
+
+    #[test]
+    fn passes_through_other_sequences() {
+        let mut parser = OscParser::new("n0nce".to_string());
+        assert_eq!(parser.feed("\x1b[31mred\x1b[0m"), vec![text("\x1b[31mred\x1b[0m")]);
+        assert_eq!(parser.feed("\x1b]0;title\x07x"), vec![text("\x1b]0;title\x07x")]);
+        assert_eq!(parser.feed("\x1b\x1b[K"), vec![text("\x1b\x1b[K")]);
+    }
+
+    #[test]
+    fn ignores_markers_without_session_nonce() {
+        let mut parser = OscParser::new("n0nce".to_string());
+        for forged in [
+            "\x1b]133;D;0\x07",
+            "\x1b]133;D;0;guess\x07",
+            "\x1b]633;E;rm -rf ~\x07",
+            "\x1b]7;file://host/etc\x07",
+        ] {
+            assert_eq!(parser.feed(forged), vec![text(forged)]);
+        }
+
+        // 未注入集成脚本的会话没有 nonce
+        let mut parser = OscParser::new(String::new());
+        assert_eq!(parser.feed("\x1b]133;A;\x07"), vec![text("\x1b]133;A;\x07")]);
+    }
+
+    #[test]
+    fn recovers_from_malformed_osc() {
+        let mut parser = OscParser::new("n0nce".to_string());
+        // OSC 被其他转义序列打断
+        assert_eq!(parser.feed("\x1b]133;A\x1b[31mred"), vec![text("\x1b]133;A\x1b[31mred")]);
+        assert_eq!(parser.feed("\x1b]133;A;n0nce\x07"), vec![event(ShellEvent::PromptStart)]);
+
+        // 未知的 133 子命令和无法解析的退出码
+        assert_eq!(parser.feed("\x1b]133;Z;n0nce\x07"), vec![text("\x1b]133;Z;n0nce\x07")]);
+        assert_eq!(parser.feed("\x1b]133;D;abc;n0nce\x07"), vec![event(ShellEvent::CommandFinished(None))]);
+        assert_eq!(parser.feed("\x1b]7;not-a-url;n0nce\x07"), vec![text("\x1b]7;not-a-url;n0nce\x07")]);
+
+        // 超长且没有结束符的 OSC 按普通输出处理
+        let long = format!("\x1b]{}", "x".repeat(MAX_OSC_LEN + 10));
+        let output: String = parser.feed(&long).into_iter()
+            .map(|item| match item {
+                ShellOutput::Text(text) => text,
+                ShellOutput::Event(event) => panic!("unexpected event {:?}", event),
+            })
+            .collect();
+        assert_eq!(output, long);
+        assert_eq!(parser.feed("\x1b]133;C;n0nce\x07"), vec![event(ShellEvent::CommandExecuted)]);
+    }
+}
@@ -0,0 +1,440 @@
+// src/storage.rs - 对话、命令和工具调用历史的本地持久化
+//
+// 数据以追加写入的 JSONL 文件保存在应用数据目录下：
+//   conversations.jsonl  每次更新写入一条完整快照，删除写入墓碑记录，最后一条为准
+//   commands.jsonl       每条执行完成的命令
+//   tool_calls.jsonl     每次 AI 工具调用
+//
+// 写入由后台线程完成，调用方（可能持有终端或对话锁）不做文件 IO；
+// 命令历史在内存中保留不含输出的索引，输出按偏移从文件读取
+
+use crate::conversation::{Conversation, ConversationInfo, CONVERSATIONS};
+use crate::error::{AppError, AppResult};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+const CONVERSATIONS_FILE: &str = "conversations.jsonl";
+const COMMANDS_FILE: &str = "commands.jsonl";
+const TOOL_CALLS_FILE: &str = "tool_calls.jsonl";
+
+// 每条记录保存的输出上限，超出部分只保留末尾
+const MAX_RECORDED_OUTPUT: usize = 64 * 1024;
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+
+// 过期快照超过该数量时在后台压缩对话日志
+const COMPACT_SLACK: usize = 200;
+
+// 退出时等待后台写入完成的最长时间
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 未初始化（如应用数据目录不可用）时只在内存中保存
+static STORAGE: Lazy<Mutex<Option<HistoryStore>>> = Lazy::new(|| Mutex::new(None));
+
+// 命令历史索引，按写入顺序排列
+static COMMAND_INDEX: Lazy<Mutex<Vec<IndexedCommand>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+// 一条执行过的终端命令
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub id: String,
+    pub session_id: String,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub started_at: u64,
+    // 原始输出（含转义序列），可直接写回终端回放
+    pub output: String,
+}
+
+// 一次 AI 工具调用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInvocation {
+    pub id: String,
+    pub conversation_id: String,
+    pub tool_call_id: String,
+    pub name: String,
+    pub arguments: String,
+    pub result: String,
+    pub success: bool,
+    pub duration_ms: u64,
+    pub timestamp: u64,
+}
+
+// conversations.jsonl 中的一行
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ConversationEntry {
+    Snapshot(Conversation),
+    Deleted { id: String },
+}
+
+// 索引中的命令记录不含输出，offset 为该行在 commands.jsonl 中的位置
+#[derive(Clone)]
+struct IndexedCommand {
+    offset: u64,
+    record: CommandRecord,
+}
+
+// 交给后台线程的写入请求
+enum WriteOp {
+    Conversation(ConversationEntry),
+    Command(CommandRecord),
+    ToolCall(ToolInvocation),
+    Flush(mpsc::Sender<()>),
+}
+
+struct HistoryStore {
+    dir: PathBuf,
+    writer: mpsc::Sender<WriteOp>,
+}
+
+impl HistoryStore {
+    fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(file)
+    }
+}
+
+// 后台写入线程的状态
+struct HistoryWriter {
+    dir: PathBuf,
+    // 对话日志的行数和仍存在的对话，用于判断何时压缩
+    conversation_lines: usize,
+    live_conversations: HashSet<String>,
+}
+
+impl HistoryWriter {
+    fn run(mut self, receiver: mpsc::Receiver<WriteOp>) {
+        for op in receiver {
+            let result = match op {
+                WriteOp::Conversation(entry) => self.write_conversation(&entry),
+                WriteOp::Command(record) => self.write_command(record),
+                WriteOp::ToolCall(record) => append_line(&self.dir.join(TOOL_CALLS_FILE), &record).map(|_| ()),
+                WriteOp::Flush(done) => {
+                    let _ = done.send(());
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    fn write_conversation(&mut self, entry: &ConversationEntry) -> AppResult<()> {
+        let path = self.dir.join(CONVERSATIONS_FILE);
+        append_line(&path, entry)?;
+        self.conversation_lines += 1;
+        match entry {
+            ConversationEntry::Snapshot(conversation) => {
+                self.live_conversations.insert(conversation.id.clone());
+            }
+            ConversationEntry::Deleted { id } => {
+                self.live_conversations.remove(id);
+            }
+        }
+
+        // 每轮对话都会追加完整快照，过期快照累积过多时压缩
+        if self.conversation_lines > self.live_conversations.len() * 2 + COMPACT_SLACK {
+            let conversations = compact_conversations(&path)?;
+            self.conversation_lines = conversations.len();
+            self.live_conversations = conversations.into_iter().map(|c| c.id).collect();
+        }
+        Ok(())
+    }
+
+    fn write_command(&mut self, mut record: CommandRecord) -> AppResult<()> {
+        let offset = append_line(&self.dir.join(COMMANDS_FILE), &record)?;
+        record.output = String::new();
+        COMMAND_INDEX.lock().unwrap().push(IndexedCommand { offset, record });
+        Ok(())
+    }
+}
+
+// 追加一行，返回该行的起始偏移
+fn append_line<T: Serialize>(path: &Path, value: &T) -> AppResult<u64> {
+    let line = serde_json::to_string(value).map_err(|e| AppError::io(e.to_string()))?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| AppError::io(format!("Failed to open {}: {}", path.display(), e)))?;
+    let offset = file.seek(SeekFrom::End(0)).map_err(|e| AppError::io(format!("Failed to write history: {}", e)))?;
+    writeln!(file, "{}", line).map_err(|e| AppError::io(format!("Failed to write history: {}", e)))?;
+    Ok(offset)
+}
+
+// 载入对话并把日志压缩成每个对话一条快照
+fn compact_conversations(path: &Path) -> AppResult<Vec<Conversation>> {
+    let mut conversations: HashMap<String, Conversation> = HashMap::new();
+    let mut line_count = 0;
+    for entry in read_jsonl::<ConversationEntry>(path)? {
+        line_count += 1;
+        match entry {
+            ConversationEntry::Snapshot(conversation) => {
+                conversations.insert(conversation.id.clone(), conversation);
+            }
+            ConversationEntry::Deleted { id } => {
+                conversations.remove(&id);
+            }
+        }
+    }
+
+    let mut conversations: Vec<Conversation> = conversations.into_values().collect();
+    conversations.sort_by_key(|c| c.updated_at);
+
+    if line_count > conversations.len() {
+        let tmp = path.with_extension("jsonl.tmp");
+        let mut file = File::create(&tmp).map_err(|e| AppError::io(format!("Failed to compact history: {}", e)))?;
+        for conversation in &conversations {
+            let line = serde_json::to_string(&ConversationEntry::Snapshot(conversation.clone()))
+                .map_err(|e| AppError::io(e.to_string()))?;
+            writeln!(file, "{}", line).map_err(|e| AppError::io(format!("Failed to compact history: {}", e)))?;
+        }
+        fs::rename(&tmp, path).map_err(|e| AppError::io(format!("Failed to compact history: {}", e)))?;
+    }
+
+    Ok(conversations)
+}
+
+// 读取 JSONL 文件，跳过损坏的行（例如写入中途崩溃留下的半行）
+fn read_jsonl<T: DeserializeOwned>(path: &Path) -> AppResult<Vec<T>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(AppError::io(format!("Failed to open {}: {}", path.display(), e))),
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| AppError::io(format!("Failed to read {}: {}", path.display(), e)))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => eprintln!("Skipping corrupt history entry in {}: {}", path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+// 读取命令历史，建立不含输出的索引
+fn load_command_index(path: &Path) -> AppResult<Vec<IndexedCommand>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(AppError::io(format!("Failed to open {}: {}", path.display(), e))),
+    };
+
+    let mut reader = BufReader::new(file);
+    let mut index = Vec::new();
+    let mut offset = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)
+            .map_err(|e| AppError::io(format!("Failed to read {}: {}", path.display(), e)))?;
+        if read == 0 {
+            break;
+        }
+        if !line.trim().is_empty() {
+            match serde_json::from_str::<CommandRecord>(&line) {
+                Ok(mut record) => {
+                    record.output = String::new();
+                    index.push(IndexedCommand { offset, record });
+                }
+                Err(e) => eprintln!("Skipping corrupt history entry in {}: {}", path.display(), e),
+            }
+        }
+        offset += read as u64;
+    }
+    Ok(index)
+}
+
+// 按偏移读取一条完整的命令记录
+fn read_command_at(path: &Path, offset: u64) -> AppResult<CommandRecord> {
+    let mut file = File::open(path).map_err(|e| AppError::io(format!("Failed to open {}: {}", path.display(), e)))?;
+    file.seek(SeekFrom::Start(offset)).map_err(|e| AppError::io(format!("Failed to read {}: {}", path.display(), e)))?;
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line)
+        .map_err(|e| AppError::io(format!("Failed to read {}: {}", path.display(), e)))?;
+    serde_json::from_str(&line).map_err(|e| AppError::io(format!("Corrupt history entry in {}: {}", path.display(), e)))
+}
+
+// 应用启动时调用：创建目录、载入已保存的对话和命令索引，并启动后台写入线程
+pub fn init(dir: PathBuf) -> AppResult<()> {
+    fs::create_dir_all(&dir).map_err(|e| AppError::io(format!("Failed to create {}: {}", dir.display(), e)))?;
+
+    let conversations = compact_conversations(&dir.join(CONVERSATIONS_FILE))?;
+    println!("[RUST] Loaded {} conversations from {}", conversations.len(), dir.display());
+    let writer = HistoryWriter {
+        dir: dir.clone(),
+        conversation_lines: conversations.len(),
+        live_conversations: conversations.iter().map(|c| c.id.clone()).collect(),
+    };
+    {
+        let mut memory = CONVERSATIONS.lock().unwrap();
+        for conversation in conversations {
+            memory.insert(conversation);
+        }
+    }
+
+    *COMMAND_INDEX.lock().unwrap() = load_command_index(&dir.join(COMMANDS_FILE))?;
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("history-writer".to_string())
+        .spawn(move || writer.run(receiver))
+        .map_err(|e| AppError::io(format!("Failed to start history writer: {}", e)))?;
+
+    *STORAGE.lock().unwrap() = Some(HistoryStore { dir, writer: sender });
+    Ok(())
+}
+
+// 应用退出前调用，等待已提交的写入完成
+pub fn flush() {
+    let (done, wait) = mpsc::channel();
+    let sent = STORAGE.lock().unwrap().as_ref().is_some_and(|store| store.writer.send(WriteOp::Flush(done)).is_ok());
+    if sent {
+        let _ = wait.recv_timeout(FLUSH_TIMEOUT);
+    }
+}
+
+fn submit(op: WriteOp) {
+    if let Some(store) = STORAGE.lock().unwrap().as_ref() {
+        if store.writer.send(op).is_err() {
+            eprintln!("History writer has stopped, dropping record");
+        }
+    }
+}
+
+pub fn save_conversation(conversation: &Conversation) {
+    submit(WriteOp::Conversation(ConversationEntry::Snapshot(conversation.clone())));
+}
+
+pub fn delete_conversation(id: &str) {
+    submit(WriteOp::Conversation(ConversationEntry::Deleted { id: id.to_string() }));
+}
+
+pub fn record_command(mut record: CommandRecord) {
+    if record.output.len() > MAX_RECORDED_OUTPUT {
+        let mut cut = record.output.len() - MAX_RECORDED_OUTPUT;
+        while !record.output.is_char_boundary(cut) {
+            cut += 1;
+        }
+        record.output.drain(..cut);
+    }
+    submit(WriteOp::Command(record));
+}
+
+pub fn record_tool_invocation(record: ToolInvocation) {
+    submit(WriteOp::ToolCall(record));
+}
+
+fn storage_path(file: &str) -> Option<PathBuf> {
+    STORAGE.lock().unwrap().as_ref().map(|store| store.path(file))
+}
+
+fn read_all<T: DeserializeOwned>(file: &str) -> AppResult<Vec<T>> {
+    match storage_path(file) {
+        Some(path) => read_jsonl(&path),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn matching_commands(query: Option<&str>, session_id: Option<&str>, limit: usize) -> Vec<IndexedCommand> {
+    let query = query.map(|q| q.to_lowercase());
+    COMMAND_INDEX.lock().unwrap()
+        .iter()
+        .rev()
+        .filter(|entry| session_id.is_none_or(|id| entry.record.session_id == id))
+        .filter(|entry| {
+            query.as_ref().is_none_or(|q| {
+                entry.record.command.to_lowercase().contains(q)
+                    || entry.record.cwd.as_ref().is_some_and(|cwd| cwd.to_lowercase().contains(q))
+            })
+        })
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+// 只在内存索引中搜索命令，最新的在前；返回的记录不含输出
+pub fn search_commands(query: Option<&str>, session_id: Option<&str>, limit: usize) -> Vec<CommandRecord> {
+    matching_commands(query, session_id, limit).into_iter().map(|entry| entry.record).collect()
+}
+
+pub fn find_command(record_id: &str) -> AppResult<CommandRecord> {
+    let offset = COMMAND_INDEX.lock().unwrap()
+        .iter()
+        .rev()
+        .find(|entry| entry.record.id == record_id)
+        .map(|entry| entry.offset)
+        .ok_or_else(|| AppError::not_found("Command record", record_id))?;
+    let path = storage_path(COMMANDS_FILE).ok_or_else(|| AppError::not_found("Command record", record_id))?;
+    read_command_at(&path, offset)
+}
+
+// Tauri 命令
+
+// 按命令或目录搜索历史命令，最新的在前
+#[tauri::command]
+pub fn search_command_history(
+    query: Option<String>,
+    session_id: Option<String>,
+    limit: Option<usize>,
+) -> AppResult<Vec<CommandRecord>> {
+    let matches = matching_commands(query.as_deref(), session_id.as_deref(), limit.unwrap_or(DEFAULT_QUERY_LIMIT));
+    let path = match storage_path(COMMANDS_FILE) {
+        Some(path) => path,
+        None => return Ok(Vec::new()),
+    };
+    matches.iter().map(|entry| read_command_at(&path, entry.offset)).collect()
+}
+
+#[tauri::command]
+pub fn get_command_record(record_id: String) -> AppResult<CommandRecord> {
+    find_command(&record_id)
+}
+
+#[tauri::command]
+pub fn list_tool_invocations(
+    conversation_id: Option<String>,
+    limit: Option<usize>,
+) -> AppResult<Vec<ToolInvocation>> {
+    let records = read_all::<ToolInvocation>(TOOL_CALLS_FILE)?;
+    Ok(records
+        .into_iter()
+        .rev()
+        .filter(|record| conversation_id.as_ref().is_none_or(|id| &record.conversation_id == id))
+        .take(limit.unwrap_or(DEFAULT_QUERY_LIMIT))
+        .collect())
+}
+
+// 按标题和消息内容搜索对话
+#[tauri::command]
+pub fn search_conversations(query: String) -> AppResult<Vec<ConversationInfo>> {
+    let query = query.to_lowercase();
+    let store = CONVERSATIONS.lock().unwrap();
+    Ok(store
+        .list()
+        .into_iter()
+        .filter(|info| {
+            info.title.to_lowercase().contains(&query)
+                || store.get(&info.id).is_some_and(|conversation| {
+                    conversation.messages.iter().any(|message| {
+                        message.content.as_ref().is_some_and(|c| c.to_lowercase().contains(&query))
+                    })
+                })
+        })
+        .collect())
+}